@@ -7,229 +7,4555 @@ implementing the CVC paradigm for both training data preprocessing
 and inference-time input normalization.
 """
 
+import argparse
+import copy
+import gzip
+import io
 import json
+import logging
+import os
+import random
 import re
-from typing import Dict, List, Tuple, Optional
+import shutil
+import sys
+import tempfile
+import time
+import unicodedata
+from collections import Counter, OrderedDict, defaultdict, deque
+from contextlib import contextmanager, nullcontext
+from concurrent.futures import ProcessPoolExecutor
+from dataclasses import dataclass
+from enum import Enum
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+from itertools import islice
+from typing import IO, Callable, Dict, Iterable, Iterator, List, Optional, Set, TextIO, Tuple, Union
 from pathlib import Path
 
 
+# Processing is logged at DEBUG level; per-word lookup decisions go to a
+# child logger so they can be enabled separately (CLI: CVC_LOG=debug)
+_logger = logging.getLogger(__name__)
+_lookup_logger = logging.getLogger(f'{__name__}.lookup')
+
+# Mapping file shipped with the dataset, located relative to this script
+BUNDLED_MAPPING_FILE = Path(__file__).resolve().parent.parent / 'mappings' / 'synonym_to_canonical.json'
+
+# Default --mapping path, relative to the working directory
+DEFAULT_MAPPING_FILE = 'mappings/synonym_to_canonical.json'
+
+# A run of word characters with any combining marks kept attached, so a
+# decomposed accent (e.g. "e" + U+0301) stays part of its word
+_COMBINING_MARKS = r'\u0300-\u036f\u1ab0-\u1aff\u1dc0-\u1dff\u20d0-\u20ff\ufe20-\ufe2f'
+_WORD_PATTERN = rf'\w(?:\w|[{_COMBINING_MARKS}])*'
+
+# Han, Hiragana and Katakana, written without spaces between words; the
+# unicode tokenizer makes each character its own word, as UAX #29 does
+# for ideographs, so multi-character keys match as phrases
+_SPACELESS_SCRIPTS = (r'\u3040-\u30ff\u31f0-\u31ff\u3400-\u4dbf\u4e00-\u9fff\uf900-\ufaff'
+                      r'\uff66-\uff9f\U00020000-\U0002fa1f')
+_UNICODE_WORD_PATTERN = re.compile(
+    rf'[{_SPACELESS_SCRIPTS}][{_COMBINING_MARKS}\u3099\u309a]*'
+    rf'|[^\W{_SPACELESS_SCRIPTS}](?:[^\W{_SPACELESS_SCRIPTS}]|[{_COMBINING_MARKS}])*'
+)
+
+# Text between two words that ends a sentence or line, so the next word
+# starts a new one unless the period follows an abbreviation (see
+# CVCProcessor.skip_midsentence_capitalized)
+_SENTENCE_BREAK_PATTERN = re.compile(r'(?:[.!?]|\n)\W*$')
+
+# Values of CVCProcessor.invalid_utf8 other than None
+INVALID_UTF8_MODES = ('pass', 'replace')
+
+# Runs of bytes that were not valid UTF-8, as decoded by the surrogateescape
+# error handler (see CVCProcessor.invalid_utf8)
+_INVALID_BYTES_PATTERN = re.compile('[\udc80-\udcff]+')
+
+# Words counted by get_vocabulary_stats
+_VOCAB_WORD_PATTERN = re.compile(r'\w+')
+
+# Whitespace-delimited tokens, and a token split into (prefix, word, suffix)
+# around its word core; compiled once as they run for every token
+_TOKEN_PATTERN = re.compile(r'\S+')
+_WORD_PARTS_PATTERN = re.compile(rf'^([^\w]*)({_WORD_PATTERN})([^\w]*)$')
+
+# Digits ending a word core right after a letter ('big5', 'v2'), split off
+# under split_trailing_digits
+_TRAILING_DIGITS_PATTERN = re.compile(r'(?<=[^\W\d_])\d+$')
+
+# Whitespace tokens that look like a URL (scheme:// or www.), an email
+# address (user@host) or a file path (any slash), left alone under skip_urls
+_URL_LIKE_PATTERN = re.compile(r'://|^\W*www\.|\w@\w|[/\\]', re.IGNORECASE)
+
+# Opening line of a fenced Markdown code block
+_MD_FENCE_PATTERN = re.compile(r'^ {0,3}(`{3,}|~{3,})')
+
+# Inline Markdown that must stay literal: code spans, the "](url)" part of
+# links and images, autolinks, and link reference definitions
+_MD_LITERAL_PATTERN = re.compile(
+    r'(`+)[\s\S]*?(?<!`)\1(?!`)'
+    r'|\]\([^)]*\)'
+    r'|<[a-z][a-z0-9+.-]*:[^>\s]*>'
+    r'|^ {0,3}\[[^\]]+\]:[^\n]*',
+    re.IGNORECASE | re.MULTILINE
+)
+
+# HTML that must stay literal: comments, CDATA sections, script and style
+# elements with their contents, doctypes, processing instructions, tags
+# (quoted attribute values may contain '>') and character references
+_HTML_LITERAL_PATTERN = re.compile(
+    r'<!--[\s\S]*?(?:-->|$)'
+    r'|<!\[CDATA\[[\s\S]*?(?:\]\]>|$)'
+    r'|<(script|style)\b(?:"[^"]*"|\'[^\']*\'|[^\'">])*>[\s\S]*?(?:</\1\s*>|$)'
+    r'|<[!?][^>]*>'
+    r'|</?[a-z][^\s/>]*(?:"[^"]*"|\'[^\']*\'|[^\'">])*>'
+    r'|&(?:#[0-9]+|#x[0-9a-f]+|[a-z][a-z0-9]*);',
+    re.IGNORECASE
+)
+
+# Shortest word considered for fuzzy matching; shorter words have too many
+# near neighbours to correct reliably
+FUZZY_MIN_WORD_LENGTH = 4
+
+# Most recent fuzzy lookups remembered per processor; older ones are evicted
+FUZZY_CACHE_SIZE = 4096
+
+# Abbreviations whose trailing period does not end a sentence
+SENTENCE_ABBREVIATIONS = frozenset({
+    'dr', 'mr', 'mrs', 'ms', 'prof', 'sr', 'jr', 'st', 'mt', 'vs', 'etc',
+    'e.g', 'i.e', 'cf', 'al', 'inc', 'ltd', 'co', 'corp', 'no', 'vol', 'fig',
+    'approx', 'dept', 'jan', 'feb', 'mar', 'apr', 'jun', 'jul', 'aug', 'sep',
+    'sept', 'oct', 'nov', 'dec'
+})
+
+# Common English function words, for excluding from vocabulary statistics
+ENGLISH_STOPWORDS = frozenset({
+    'a', 'about', 'after', 'all', 'also', 'am', 'an', 'and', 'any', 'are',
+    'as', 'at', 'be', 'because', 'been', 'before', 'being', 'but', 'by',
+    'can', 'could', 'did', 'do', 'does', 'for', 'from', 'had', 'has', 'have',
+    'he', 'her', 'here', 'him', 'his', 'how', 'i', 'if', 'in', 'into', 'is',
+    'it', 'its', 'me', 'my', 'no', 'not', 'of', 'on', 'or', 'our', 'she',
+    'should', 'so', 'some', 'than', 'that', 'the', 'their', 'them', 'then',
+    'there', 'these', 'they', 'this', 'those', 'to', 'up', 'us', 'was', 'we',
+    'were', 'what', 'when', 'where', 'which', 'while', 'who', 'will', 'with',
+    'would', 'you', 'your'
+})
+
+
+class CVCError(Exception):
+    """Base class for errors raised by the CVC processor."""
+
+
+class MappingFileError(CVCError):
+    """The mapping file could not be read."""
+
+
+class MappingParseError(CVCError):
+    """The mapping file is not valid JSON."""
+
+
+class InvalidMappingError(CVCError, ValueError):
+    """Mapping data is structurally invalid, or a mapping edit is inconsistent."""
+
+
+class InputFileError(CVCError):
+    """A file to be processed could not be read, or is not valid UTF-8."""
+
+
+class ExpandStrategy(Enum):
+    """How expand_text picks a synonym for a canonical word."""
+    FIRST_SYNONYM = 'first'
+    LONGEST_SYNONYM = 'longest'
+    RANDOM = 'random'
+
+
+class CaseStrategy(Enum):
+    """How a replacement's capitalization is derived from the original word."""
+    # ALL CAPS stays all caps, Title stays title, anything else becomes lower
+    PRESERVE = 'preserve'
+    # Copy the original's case character by character
+    EXACT = 'exact'
+    # Match only whether the first letter is uppercase
+    MATCH_FIRST_LETTER = 'first_letter'
+    # Uppercase only when the original is all caps
+    MATCH_ALL_CAPS = 'all_caps'
+    # Always lowercase
+    FORCE_LOWER = 'lower'
+    # Use the replacement exactly as stored in the mappings
+    FORCE_CANONICAL = 'canonical'
+
+
+class Tokenizer(Enum):
+    """How process_text splits input into words."""
+    # Split on whitespace and strip leading/trailing punctuation (fast path)
+    WHITESPACE = 'whitespace'
+    # Match runs of Unicode word characters anywhere, keeping the text between verbatim
+    UNICODE = 'unicode'
+
+
+@dataclass
+class ValidationError:
+    """A single consistency problem found in loaded mapping data."""
+    kind: str
+    message: str
+
+
+@dataclass
+class KeptToken:
+    """A span of text left unchanged by CVCProcessor.tokens."""
+    text: str
+    start: int
+    end: int
+
+
+@dataclass
+class ReplacedToken:
+    """A word or phrase CVCProcessor.tokens replaced with its canonical form."""
+    original: str
+    canonical: str
+    start: int
+    end: int
+
+
+Token = Union[KeptToken, ReplacedToken]
+
+
+@dataclass
+class MappingDiff:
+    """Synonym-level differences between two sets of mapping data."""
+    # (synonym, canonical) pairs only in the new data
+    added: List[Tuple[str, str]]
+    # (synonym, canonical) pairs only in the old data
+    removed: List[Tuple[str, str]]
+    # (synonym, old canonical, new canonical) for synonyms that moved
+    reassigned: List[Tuple[str, str, str]]
+
+    def is_empty(self) -> bool:
+        """Whether the two sets of mappings are equivalent."""
+        return not (self.added or self.removed or self.reassigned)
+
+
+@dataclass
+class ProgressEvent:
+    """Periodic progress report from process_file_with_progress."""
+    lines_processed: int
+    bytes_processed: int
+    # Size of the input in bytes, when known
+    total_bytes: Optional[int] = None
+
+
+def _mapping_format(path: Union[str, os.PathLike]) -> str:
+    """Guess a mapping file's format ('json', 'yaml' or 'toml') from its extension."""
+    path = Path(path)
+    if path.suffix.lower() == '.gz':
+        path = path.with_suffix('')
+    suffix = path.suffix.lower()
+    if suffix in ('.yaml', '.yml'):
+        return 'yaml'
+    if suffix == '.toml':
+        return 'toml'
+    return 'json'
+
+
+def _load_mapping_file(mapping_file: Union[str, os.PathLike]):
+    """Read and parse a mapping file, decompressing .gz files."""
+    opener = gzip.open if Path(mapping_file).suffix.lower() == '.gz' else open
+    try:
+        f = opener(mapping_file, 'rt')
+    except OSError as e:
+        raise MappingFileError(f"Failed to read mapping file {mapping_file}: {e}") from e
+
+    with f:
+        return _read_mappings(f, _mapping_format(mapping_file), str(mapping_file))
+
+
+def _read_mappings(reader: IO, fmt: str, source: str):
+    """Read and parse mapping data from a text or binary stream."""
+    try:
+        text = reader.read()
+        if isinstance(text, bytes):
+            text = text.decode('utf-8')
+    except (OSError, EOFError, UnicodeDecodeError) as e:
+        raise MappingFileError(f"Failed to read mapping file {source}: {e}") from e
+
+    return _parse_mappings(text, fmt, source)
+
+
+def _parse_mappings(text: str, fmt: str, source: str):
+    """Parse mapping file text in the given format, raising MappingParseError."""
+    if fmt not in ('json', 'yaml', 'toml'):
+        raise ValueError(f"unknown mapping format '{fmt}' (expected 'json', 'yaml' or 'toml')")
+
+    try:
+        if fmt == 'yaml':
+            try:
+                import yaml
+            except ImportError:
+                raise MappingParseError(
+                    "Loading YAML mappings requires PyYAML: pip install pyyaml"
+                ) from None
+            return yaml.safe_load(text)
+        if fmt == 'toml':
+            try:
+                import tomllib
+            except ImportError:  # Python < 3.11
+                try:
+                    import tomli as tomllib
+                except ImportError:
+                    raise MappingParseError(
+                        "Loading TOML mappings on Python < 3.11 requires tomli: pip install tomli"
+                    ) from None
+            return tomllib.loads(text)
+        return json.loads(text)
+    except MappingParseError:
+        raise
+    except Exception as e:
+        raise MappingParseError(f"Failed to parse {fmt.upper()} mappings from {source}: {e}") from e
+
+
+def _check_mapping_structure(data):
+    """Raise InvalidMappingError unless data has the mapping file structure."""
+    if not isinstance(data, dict):
+        raise InvalidMappingError("mapping data must be a JSON object")
+
+    mappings = data.get('mappings')
+    if not isinstance(mappings, dict):
+        raise InvalidMappingError("mapping data must contain a 'mappings' object")
+
+    for key, info in mappings.items():
+        if not isinstance(info, dict) or not isinstance(info.get('canonical'), str) \
+                or not info['canonical']:
+            raise InvalidMappingError(f"mapping '{key}' must have a non-empty 'canonical' string")
+        synonyms = info.get('synonyms')
+        if not isinstance(synonyms, list) or not all(isinstance(s, str) and s for s in synonyms):
+            raise InvalidMappingError(f"mapping '{key}' must have a 'synonyms' list of strings")
+        if not isinstance(info.get('domain', ''), str):
+            raise InvalidMappingError(f"mapping '{key}' has a 'domain' that is not a string")
+        rank = info.get('frequency_rank')
+        if rank is not None and (isinstance(rank, bool) or not isinstance(rank, int)):
+            raise InvalidMappingError(f"mapping '{key}' has a 'frequency_rank' that is not an integer")
+
+    reverse_lookup = data.get('reverse_lookup')
+    if reverse_lookup is not None:
+        if not isinstance(reverse_lookup, dict) or not all(
+                isinstance(k, str) and k and isinstance(v, str) and v
+                for k, v in reverse_lookup.items()):
+            raise InvalidMappingError("'reverse_lookup' must map non-empty strings to strings")
+
+    metadata = data.get('metadata')
+    if metadata is not None and not isinstance(metadata, dict):
+        raise InvalidMappingError("'metadata' must be an object")
+
+
+def merge_mapping_data(datasets: Iterable[Tuple[str, Dict]],
+                       conflict: str = 'last') -> Tuple[Dict, Dict[str, str]]:
+    """
+    Merge several sets of mapping data in priority order.
+
+    A conflict is a mapping key present in more than one dataset, or a synonym
+    mapped to different canonicals. A synonym that loses a conflict is also
+    dropped from the losing entry's synonym list.
+
+    Args:
+        datasets: (source name, mapping data) pairs, lowest priority first
+        conflict: 'last' (later datasets win), 'first' (earlier datasets win)
+            or 'error' (raise InvalidMappingError)
+
+    Returns:
+        Tuple of (merged mapping data, mapping key -> source it came from).
+        The merged metadata has recomputed totals and lists the sources
+        under 'merged_from'.
+
+    Raises:
+        InvalidMappingError: If a dataset does not have the mapping file
+            structure, or on a conflict under conflict='error'
+    """
+    if conflict not in ('last', 'first', 'error'):
+        raise ValueError(f"unknown conflict policy '{conflict}'")
+
+    mappings: Dict[str, Dict] = {}
+    reverse_lookup: Dict[str, str] = {}
+    sources: Dict[str, str] = {}
+    synonym_sources: Dict[str, str] = {}
+    metadata: Dict = {}
+
+    for source, data in datasets:
+        _check_mapping_structure(data)
+
+        for key, info in data['mappings'].items():
+            if key in mappings:
+                if conflict == 'error':
+                    raise InvalidMappingError(
+                        f"mapping '{key}' is defined in both {sources[key]} and {source}"
+                    )
+                if conflict == 'first':
+                    continue
+            mappings[key] = copy.deepcopy(info)
+            sources[key] = source
+
+        lookup = data.get('reverse_lookup') or build_reverse_lookup(data['mappings'])
+        for synonym, canonical in lookup.items():
+            previous = reverse_lookup.get(synonym)
+            if previous is not None and previous != canonical:
+                if conflict == 'error':
+                    raise InvalidMappingError(
+                        f"'{synonym}' maps to '{previous}' in {synonym_sources[synonym]} "
+                        f"and to '{canonical}' in {source}"
+                    )
+                if conflict == 'first':
+                    continue
+            reverse_lookup[synonym] = canonical
+            synonym_sources[synonym] = source
+
+        if conflict == 'first':
+            metadata = {**data.get('metadata', {}), **metadata}
+        else:
+            metadata.update(data.get('metadata', {}))
+
+    # Keep each entry's synonym list consistent with the winning reverse lookup
+    for info in mappings.values():
+        info['synonyms'] = [
+            synonym for synonym in info['synonyms']
+            if reverse_lookup.get(synonym, info['canonical']) == info['canonical']
+        ]
+
+    metadata['total_mappings'] = len(mappings)
+    metadata['total_synonyms'] = sum(len(info['synonyms']) for info in mappings.values())
+    metadata['merged_from'] = list(dict.fromkeys(sources.values()))
+
+    merged = {'metadata': metadata, 'mappings': mappings, 'reverse_lookup': reverse_lookup}
+    return merged, sources
+
+
+def diff_mappings(old: Dict, new: Dict) -> MappingDiff:
+    """
+    Compare two sets of mapping data synonym by synonym.
+
+    Canonical words mapping to themselves are not reported.
+
+    Args:
+        old: Mapping data before the change
+        new: Mapping data after the change
+
+    Returns:
+        MappingDiff with added, removed and reassigned synonyms, sorted by synonym
+
+    Raises:
+        InvalidMappingError: If either does not have the mapping file structure
+    """
+    lookups = []
+    for data in (old, new):
+        _check_mapping_structure(data)
+        lookup = data.get('reverse_lookup') or build_reverse_lookup(data['mappings'])
+        lookups.append({k: v for k, v in lookup.items() if k.lower() != v.lower()})
+    old_lookup, new_lookup = lookups
+
+    return MappingDiff(
+        added=sorted((k, v) for k, v in new_lookup.items() if k not in old_lookup),
+        removed=sorted((k, v) for k, v in old_lookup.items() if k not in new_lookup),
+        reassigned=sorted(
+            (k, old_lookup[k], v) for k, v in new_lookup.items()
+            if k in old_lookup and old_lookup[k] != v
+        )
+    )
+
+
+def build_trie_pattern(words: Iterable[str]) -> str:
+    """
+    Build a regex matching any of the given words, structured as a trie.
+
+    Alternatives are nested by shared prefix so the regex engine does not
+    try every word in turn, which keeps matching fast for large vocabularies.
+    Spaces inside multi-word entries match any run of whitespace.
+
+    Args:
+        words: Words (or phrases) to match
+
+    Returns:
+        Regex pattern string (without anchors or flags)
+    """
+    trie: Dict = {}
+    for word in words:
+        node = trie
+        for ch in word:
+            node = node.setdefault(ch, {})
+        node[''] = {}
+
+    def emit(node: Dict) -> str:
+        branches = [
+            (r'\s+' if ch == ' ' else re.escape(ch)) + emit(child)
+            for ch, child in sorted(node.items()) if ch
+        ]
+        if not branches:
+            return ''
+        body = branches[0] if len(branches) == 1 else '(?:' + '|'.join(branches) + ')'
+        return f'(?:{body})?' if '' in node else body
+
+    return emit(trie)
+
+
+def fold_accents(text: str) -> str:
+    """Strip diacritics from text (e.g. 'être' -> 'etre')."""
+    decomposed = unicodedata.normalize('NFD', text)
+    return unicodedata.normalize('NFC', ''.join(c for c in decomposed if not unicodedata.combining(c)))
+
+
+def edit_distance(a: str, b: str, limit: Optional[int] = None) -> int:
+    """
+    Levenshtein distance between two strings.
+
+    Args:
+        a: First string
+        b: Second string
+        limit: If given, stop early and return limit + 1 once the distance
+            is known to exceed it
+
+    Returns:
+        Number of single-character insertions, deletions and substitutions
+    """
+    previous = list(range(len(b) + 1))
+    for i, ca in enumerate(a, 1):
+        current = [i]
+        for j, cb in enumerate(b, 1):
+            current.append(min(previous[j] + 1, current[j - 1] + 1, previous[j - 1] + (ca != cb)))
+        if limit is not None and min(current) > limit:
+            return limit + 1
+        previous = current
+    return previous[-1]
+
+
+def build_reverse_lookup(mappings: Dict) -> Dict[str, str]:
+    """
+    Derive a synonym-to-canonical lookup from mapping entries.
+
+    Later mappings win when a synonym is listed more than once, and every
+    canonical also maps to itself unless it is listed as another's synonym.
+
+    Args:
+        mappings: The 'mappings' section of a mapping file
+
+    Returns:
+        Dictionary mapping each synonym (and canonical) to its canonical form
+    """
+    reverse_lookup = {}
+    for info in mappings.values():
+        canonical = info['canonical']
+        for synonym in info['synonyms']:
+            reverse_lookup[synonym] = canonical
+
+    for info in mappings.values():
+        reverse_lookup.setdefault(info['canonical'], info['canonical'])
+
+    return reverse_lookup
+
+
+def find_conflicting_synonyms(mappings: Dict) -> List[ValidationError]:
+    """
+    Find synonyms listed under more than one canonical.
+
+    Args:
+        mappings: The 'mappings' section of a mapping file
+
+    Returns:
+        A 'conflicting_synonym' problem per such synonym, in listing order
+    """
+    owners: Dict[str, List[str]] = {}
+    for info in mappings.values():
+        for synonym in info['synonyms']:
+            owners.setdefault(synonym, []).append(info['canonical'])
+
+    return [
+        ValidationError(
+            'conflicting_synonym',
+            f"'{synonym}' is listed under multiple canonicals: {', '.join(listed_under)}"
+        )
+        for synonym, listed_under in owners.items() if len(set(listed_under)) > 1
+    ]
+
+
+def _case_fold_rank(key: str) -> Tuple[bool, str]:
+    """Sort key picking which of several case variants a folded form maps by."""
+    return key != key.lower(), key
+
+
+def build_case_insensitive_lookup(reverse_lookup: Dict[str, str]) -> Dict[str, str]:
+    """
+    Derive the lowercased lookup used for case-insensitive matching.
+
+    When keys differ only in case (e.g. 'US' and 'us'), the folded form takes
+    the canonical of the all-lowercase key if there is one, otherwise that of
+    the key sorting first, so the result does not depend on key order.
+
+    Args:
+        reverse_lookup: Synonym-to-canonical lookup
+
+    Returns:
+        Dictionary mapping each lowercased key to its canonical form
+    """
+    winners = {}
+    for key in reverse_lookup:
+        folded = key.lower()
+        if folded not in winners or _case_fold_rank(key) < _case_fold_rank(winners[folded]):
+            winners[folded] = key
+
+    return {folded: reverse_lookup[key] for folded, key in winners.items()}
+
+
 class CVCProcessor:
     """Processes text using canonical vocabulary compression."""
 
-    def __init__(self, mapping_file: str):
-        """
-        Initialize CVC processor with synonym mappings.
+    def __init__(self, mapping_file: Union[str, os.PathLike], strict: bool = False):
+        """
+        Initialize CVC processor with synonym mappings.
+
+        The format is chosen by extension: .yaml/.yml for YAML (requires
+        PyYAML), .toml for TOML, and JSON otherwise. A trailing .gz (e.g.
+        mappings.json.gz) is decompressed transparently.
+
+        Synonyms listed under more than one canonical are reported in
+        load_warnings; which canonical they get depends on reverse_lookup.
+
+        Args:
+            mapping_file: Path to file containing synonym-to-canonical mappings
+            strict: Raise instead of warning about conflicting synonyms
+
+        Raises:
+            MappingFileError: If the file cannot be read
+            MappingParseError: If the file cannot be parsed
+            InvalidMappingError: If the data does not have the mapping file
+                structure, or has conflicting synonyms under strict
+        """
+        self._set_default_options()
+        self._load_mappings(_load_mapping_file(mapping_file), strict)
+
+    @classmethod
+    def from_files(cls, mapping_files: Iterable[Union[str, os.PathLike]], conflict: str = 'last',
+                   strict: bool = False) -> 'CVCProcessor':
+        """
+        Create a CVC processor from several mapping files merged in order.
+
+        Typically a base vocabulary followed by domain overlays. The file each
+        mapping entry came from is recorded in mapping_sources.
+
+        Args:
+            mapping_files: Paths to mapping files, lowest priority first
+            conflict: Resolution for a mapping key or synonym defined in more
+                than one file (see merge_mapping_data)
+            strict: Raise instead of warning about conflicting synonyms in the
+                merged data
+
+        Returns:
+            Initialized CVCProcessor
+
+        Raises:
+            MappingFileError: If a file cannot be read
+            MappingParseError: If a file cannot be parsed
+            InvalidMappingError: If a file does not have the mapping file
+                structure, on a conflict under conflict='error', or on
+                conflicting synonyms under strict
+        """
+        datasets = [(str(path), _load_mapping_file(path)) for path in mapping_files]
+        data, sources = merge_mapping_data(datasets, conflict)
+
+        processor = cls.from_mappings(data, strict)
+        processor.mapping_sources = sources
+        return processor
+
+    @classmethod
+    def from_mappings(cls, data: Dict, strict: bool = False) -> 'CVCProcessor':
+        """
+        Create a CVC processor from already-parsed mapping data.
+
+        Args:
+            data: Mapping data with the same structure as the JSON mapping file
+            strict: Raise instead of warning about conflicting synonyms
+
+        Returns:
+            Initialized CVCProcessor
+
+        Raises:
+            InvalidMappingError: If the data does not have the mapping file
+                structure, or has conflicting synonyms under strict
+        """
+        processor = cls.__new__(cls)
+        processor._set_default_options()
+        processor._load_mappings(data, strict)
+        return processor
+
+    @classmethod
+    def from_reader(cls, reader: IO, fmt: str = 'json', strict: bool = False) -> 'CVCProcessor':
+        """
+        Create a CVC processor from mapping data read from a file-like object.
+
+        Args:
+            reader: Text or binary (UTF-8) stream, e.g. an HTTP response body
+            fmt: Data format: 'json', 'yaml' or 'toml'
+            strict: Raise instead of warning about conflicting synonyms
+
+        Returns:
+            Initialized CVCProcessor
+
+        Raises:
+            MappingFileError: If reading from the stream fails
+            MappingParseError: If the data cannot be parsed
+            InvalidMappingError: If the data does not have the mapping file
+                structure, or has conflicting synonyms under strict
+            ValueError: If fmt is not a supported format
+        """
+        return cls.from_mappings(_read_mappings(reader, fmt, '<reader>'), strict)
+
+    @classmethod
+    def from_yaml_str(cls, text: str) -> 'CVCProcessor':
+        """
+        Create a CVC processor from mapping data in YAML form (requires PyYAML).
+
+        Args:
+            text: YAML document with the same structure as the JSON mapping file
+
+        Returns:
+            Initialized CVCProcessor
+        """
+        return cls.from_mappings(_parse_mappings(text, 'yaml', '<string>'))
+
+    @classmethod
+    def from_toml_str(cls, text: str) -> 'CVCProcessor':
+        """
+        Create a CVC processor from mapping data in TOML form.
+
+        Args:
+            text: TOML document with the same structure as the JSON mapping file
+
+        Returns:
+            Initialized CVCProcessor
+        """
+        return cls.from_mappings(_parse_mappings(text, 'toml', '<string>'))
+
+    @classmethod
+    def with_bundled_data(cls) -> 'CVCProcessor':
+        """
+        Create a CVC processor from the mapping file shipped with the dataset.
+
+        Returns:
+            Initialized CVCProcessor
+        """
+        return cls(str(BUNDLED_MAPPING_FILE))
+
+    def _set_default_options(self):
+        """Initialize processing options to their default values."""
+        # Longest synonym phrase (in words) considered during matching
+        self.max_phrase_len = 1
+
+        # Word splitting strategy used by process_text
+        self.tokenizer = Tokenizer.WHITESPACE
+
+        # Placed between tokens when process_text and expand_text rejoin
+        # whitespace tokens (the unicode tokenizer, and file and stream
+        # processing, keep the original spacing)
+        self.token_separator = ' '
+
+        # Process hyphenated compounds and contractions instead of skipping them
+        self.split_compounds = False
+
+        # Capitalization applied to replacements when preserve_case is True
+        self.case_strategy = CaseStrategy.PRESERVE
+
+        # Pre-scan text with a compiled pattern of all keys so only tokens that
+        # can match are looked up (whitespace tokenizer only)
+        self.use_automaton = False
+
+        # Domains whose mappings are applied (None means all domains)
+        self.active_domains: Optional[Set[str]] = None
+
+        # Inclusive frequency_rank bounds a mapping must fall within (None means unbounded)
+        self.min_frequency_rank: Optional[int] = None
+        self.max_frequency_rank: Optional[int] = None
+
+        # Follow a->b->c chains in reverse_lookup to their terminal canonical
+        self.resolve_chains = False
+
+        # Lowercased words and phrases that are never replaced, even if mapped
+        self.never_replace: Set[str] = set()
+
+        # Only match keys exactly as written (no case-insensitive fallback)
+        self.case_sensitive = False
+
+        # Fall back to matching with diacritics stripped from words and keys
+        self.accent_insensitive = False
+
+        # Maximum edit distance for matching misspelled words (0 disables it)
+        self.fuzzy_distance = 0
+
+        # Pass tokens that look like URLs, email addresses or paths through untouched
+        self.skip_urls = False
+
+        # Leave words shorter than this many characters unchanged
+        self.min_word_len = 0
+
+        # Leave all-caps words (likely acronyms or names) unchanged
+        self.skip_all_caps = False
+
+        # Count only whitespace tokens containing a word character toward
+        # total_words, so punctuation like '--' or '...' doesn't dilute rates
+        self.count_only_word_tokens = False
+
+        # Treat digits ending a word as part of its suffix, so 'large2' is
+        # looked up as 'large' (whitespace tokenizer only)
+        self.split_trailing_digits = False
+
+        # Leave Title-Case words (likely proper nouns) unchanged unless they
+        # start a line or follow a sentence-ending '.', '!' or '?' (a period
+        # after an abbreviation or initial such as "Dr." or "J." does not count)
+        self.skip_midsentence_capitalized = False
+
+        # Unicode normalization form ('NFC', 'NFKC', ...) applied to words and
+        # keys when a word has no direct match (None for byte-exact matching)
+        self.unicode_normalization: Optional[str] = 'NFC'
+
+        # Handling of input bytes that are not valid UTF-8 in file and stream
+        # processing: None fails, 'pass' copies them to the output unchanged,
+        # 'replace' writes one U+FFFD per run of invalid bytes
+        self.invalid_utf8: Optional[str] = None
+
+        # Abort file and stream processing on the first line that fails,
+        # instead of passing it through unchanged and recording the error
+        self.fail_fast = False
+
+        # Intern replaced words so repeated replacements share one string
+        # instead of each holding its own cased copy of the canonical
+        self.intern_canonicals = False
+
+    def set_active_domains(self, domains: Optional[Iterable[str]]):
+        """
+        Restrict replacements to mappings from the given domains.
+
+        Args:
+            domains: Domain names to keep active, or None to apply all domains
+        """
+        self.active_domains = set(domains) if domains is not None else None
+
+    def set_never_replace(self, words: Iterable[str]):
+        """
+        Protect words from replacement regardless of the mappings.
+
+        Args:
+            words: Words or phrases to keep verbatim (matched case-insensitively)
+        """
+        self.never_replace = {w.lower() for w in words}
+
+    def _load_mappings(self, data: Dict, strict: bool = False):
+        """Populate lookup tables from parsed mapping data."""
+        _check_mapping_structure(data)
+
+        # Problems that don't prevent loading but likely mean a dataset bug
+        load_warnings = find_conflicting_synonyms(data['mappings'])
+        if strict and load_warnings:
+            raise InvalidMappingError(load_warnings[0].message)
+        self.load_warnings: List[ValidationError] = load_warnings
+
+        self.mappings = data['mappings']
+        # Derive the reverse lookup when the file doesn't provide one
+        self.reverse_lookup = data.get('reverse_lookup') or build_reverse_lookup(self.mappings)
+        self.metadata = data.get('metadata', {})
+
+        # Mapping key -> file it was loaded from (filled in by from_files)
+        self.mapping_sources: Dict[str, str] = {}
+
+        # Build case-insensitive lookup for better matching
+        self.case_insensitive_lookup = build_case_insensitive_lookup(self.reverse_lookup)
+
+        # Case-insensitive lookup with diacritics stripped, for accent_insensitive
+        self.accent_insensitive_lookup = {
+            fold_accents(k): v for k, v in self.case_insensitive_lookup.items()
+        }
+
+        # Canonical word -> mapping info, used for expansion
+        self.canonical_lookup = {
+            info['canonical'].lower(): info for info in self.mappings.values()
+        }
+
+        # Compiled on first use by _candidate_starts
+        self._automaton = None
+
+        # Built on first use by _lookup_normalized
+        self._normalized_lookup = None
+
+        # Built on first use by _lookup_fuzzy
+        self._fuzzy_index = None
+
+        # Set by clone() while the lookup tables are shared with another processor
+        self._maps_shared = False
+
+    def reload(self, mapping_file: Union[str, os.PathLike], strict: bool = False):
+        """
+        Replace the processor's mappings with those from a mapping file.
+
+        The file is read and all lookup tables are built before anything is
+        changed, so if loading fails the processor is left exactly as it was.
+        The new tables are then swapped in with a single update, so a thread
+        processing text concurrently sees either the old mappings or the new
+        ones, never a mix. Options are kept, and clones made earlier keep the
+        old mappings.
+
+        Args:
+            mapping_file: Path to a mapping file, in any format accepted by
+                the constructor
+            strict: Raise instead of warning about conflicting synonyms
+
+        Raises:
+            MappingFileError: If the file cannot be read
+            MappingParseError: If the file cannot be parsed
+            InvalidMappingError: If the data does not have the mapping file
+                structure, or has conflicting synonyms under strict
+        """
+        tables = CVCProcessor.__new__(CVCProcessor)
+        tables._load_mappings(_load_mapping_file(mapping_file), strict)
+        vars(self).update(vars(tables))
+
+    def clone(self) -> 'CVCProcessor':
+        """
+        Create a copy of the processor that shares its lookup tables.
+
+        Cloning is cheap regardless of mapping size: the mappings and lookup
+        tables are not copied. Options are independent per clone. Processing
+        only reads the tables, so clones can be used side by side; the first
+        add_mapping or remove_mapping on any of them copies the tables for that
+        processor so the others are unaffected.
+
+        Returns:
+            New processor with the same mappings and options
+        """
+        clone = copy.copy(self)
+        clone._own_options()
+        self._maps_shared = clone._maps_shared = True
+        return clone
+
+    def filtered(self, predicate: Callable[[Dict], bool]) -> 'CVCProcessor':
+        """
+        Create a processor with only the mappings passing a predicate.
+
+        All lookup tables are rebuilt from the kept mappings and the metadata
+        totals reflect the subset. Options are copied from this processor.
+
+        Example:
+            top = processor.filtered(lambda info: info.get('frequency_rank', 1) <= 100)
+
+        Args:
+            predicate: Called with each mapping info dict; entries for which
+                it returns True are kept
+
+        Returns:
+            New processor restricted to the kept mappings
+        """
+        data = self.to_mappings_data()
+        mappings = {key: info for key, info in data['mappings'].items() if predicate(info)}
+        kept = {info['canonical'] for info in mappings.values()}
+
+        data['mappings'] = mappings
+        data['reverse_lookup'] = {
+            synonym: canonical for synonym, canonical in data['reverse_lookup'].items()
+            if canonical in kept
+        }
+        data['metadata']['total_mappings'] = len(mappings)
+        data['metadata']['total_synonyms'] = sum(len(info['synonyms']) for info in mappings.values())
+
+        processor = copy.copy(self)
+        processor._own_options()
+        processor._load_mappings(data)
+        processor.mapping_sources = {
+            key: source for key, source in self.mapping_sources.items() if key in mappings
+        }
+        return processor
+
+    def _own_options(self):
+        """Copy set-valued options shared by copy.copy so updates stay per processor."""
+        self.never_replace = set(self.never_replace)
+        if self.active_domains is not None:
+            self.active_domains = set(self.active_domains)
+
+    def _own_maps(self):
+        """Copy lookup tables shared through clone() before modifying them."""
+        if not self._maps_shared:
+            return
+
+        self.mappings = copy.deepcopy(self.mappings)
+        self.reverse_lookup = dict(self.reverse_lookup)
+        self.metadata = dict(self.metadata)
+        self.mapping_sources = dict(self.mapping_sources)
+        self.case_insensitive_lookup = dict(self.case_insensitive_lookup)
+        self.accent_insensitive_lookup = dict(self.accent_insensitive_lookup)
+        self.canonical_lookup = {
+            info['canonical'].lower(): info for info in self.mappings.values()
+        }
+        self._maps_shared = False
+
+    def add_mapping(self, synonym: str, canonical: str, domain: str = 'general'):
+        """
+        Map a synonym to a canonical word, updating all lookup tables.
+
+        If the synonym already maps elsewhere it is moved. The domain is only
+        used when the canonical word has no mapping entry yet.
+
+        Args:
+            synonym: Word to be replaced
+            canonical: Canonical form to replace it with
+            domain: Domain for a newly created mapping entry
+
+        Raises:
+            InvalidMappingError: If either word is empty, the synonym equals the
+                canonical, the synonym is itself another mapping's canonical, or
+                the canonical is a synonym of a different canonical (which would
+                form a chain)
+        """
+        if not synonym or not canonical:
+            raise InvalidMappingError("synonym and canonical must be non-empty")
+        if synonym.lower() == canonical.lower():
+            raise InvalidMappingError(f"cannot map '{synonym}' to itself")
+        if synonym.lower() in self.canonical_lookup:
+            raise InvalidMappingError(f"'{synonym}' is already a canonical word")
+        mapped = self.case_insensitive_lookup.get(canonical.lower())
+        if mapped is not None and mapped.lower() != canonical.lower():
+            raise InvalidMappingError(f"'{canonical}' is already a synonym of '{mapped}'")
+
+        self._own_maps()
+        if synonym in self.reverse_lookup:
+            self.remove_mapping(synonym)
+
+        info = self.canonical_lookup.get(canonical.lower())
+        if info is None:
+            key = f"{domain}_{canonical}"
+            while key in self.mappings:
+                key += '_'
+            info = {
+                'canonical': canonical,
+                'synonyms': [],
+                'frequency_rank': 1,
+                'domain': domain
+            }
+            self.mappings[key] = info
+            self.canonical_lookup[canonical.lower()] = info
+
+        if synonym not in info['synonyms']:
+            info['synonyms'].append(synonym)
+        self.reverse_lookup[synonym] = info['canonical']
+        self._refresh_case_insensitive(synonym.lower())
+        self.accent_insensitive_lookup[fold_accents(synonym.lower())] = info['canonical']
+        self._automaton = None
+        self._normalized_lookup = None
+        self._fuzzy_index = None
+
+    def remove_mapping(self, synonym: str):
+        """
+        Remove a synonym's mapping, updating all lookup tables.
+
+        Args:
+            synonym: Word whose mapping should be removed (exact case)
+        """
+        if synonym not in self.reverse_lookup:
+            raise InvalidMappingError(f"'{synonym}' has no mapping")
+
+        self._own_maps()
+        canonical = self.reverse_lookup.pop(synonym)
+        info = self.canonical_lookup.get(canonical.lower())
+        if info and synonym in info['synonyms']:
+            info['synonyms'].remove(synonym)
+
+        # Another key may still share the same case-folded form
+        folded = synonym.lower()
+        self._refresh_case_insensitive(folded)
+
+        accentless = fold_accents(folded)
+        remaining = [k for k in self.case_insensitive_lookup if fold_accents(k) == accentless]
+        if remaining:
+            self.accent_insensitive_lookup[accentless] = self.case_insensitive_lookup[remaining[-1]]
+        else:
+            self.accent_insensitive_lookup.pop(accentless, None)
+        self._automaton = None
+        self._normalized_lookup = None
+        self._fuzzy_index = None
+
+    def _refresh_case_insensitive(self, folded: str):
+        """Recompute one case-insensitive entry after its keys changed."""
+        keys = [k for k in self.reverse_lookup if k.lower() == folded]
+        if keys:
+            self.case_insensitive_lookup[folded] = self.reverse_lookup[min(keys, key=_case_fold_rank)]
+        else:
+            self.case_insensitive_lookup.pop(folded, None)
+
+    def to_mappings_data(self) -> Dict:
+        """
+        Build mapping data reflecting the processor's current state.
+
+        Returns:
+            Dictionary in the mapping file format, with metadata totals recomputed
+        """
+        mappings = copy.deepcopy(self.mappings)
+        metadata = dict(self.metadata)
+        metadata['total_mappings'] = len(mappings)
+        metadata['total_synonyms'] = sum(len(info['synonyms']) for info in mappings.values())
+
+        return {
+            'metadata': metadata,
+            'mappings': mappings,
+            'reverse_lookup': dict(self.reverse_lookup)
+        }
+
+    def save_to_file(self, path: str):
+        """
+        Write the processor's current mappings to a JSON mapping file.
+
+        Args:
+            path: Destination path for the mapping file
+        """
+        with open(path, 'w') as f:
+            json.dump(self.to_mappings_data(), f, indent=2)
+
+    def process_text(self, text: str, preserve_case: bool = True) -> Tuple[str, Dict]:
+        """
+        Apply CVC transformation to input text.
+
+        Text is scanned left to right and at each word the longest mapping
+        key starting there wins, up to max_phrase_len words. A key that is a
+        canonical phrase itself (e.g. 'new york') also wins, leaving its words
+        unchanged rather than letting a shorter key (e.g. 'new') match inside
+        it. Keys of equal length at one position can only differ in case: an
+        exact-case key wins, then the rule of build_case_insensitive_lookup.
+
+        Processing the output again leaves it unchanged, unless a canonical is
+        itself a synonym (a chain such as vivid -> bright -> smart, reported
+        by validate): by default each pass follows one link. resolve_chains
+        follows chains to their end in one pass, which makes the output
+        stable for any mappings without cycles.
+
+        Args:
+            text: Input text to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Tuple of (processed_text, statistics). Each replacement records
+            its word 'position' and the 'start'/'end' indices of the original
+            word or phrase in text, so text[start:end] is its source span.
+            Replacements found by fuzzy matching also carry 'fuzzy': True.
+
+            'replacement_rate' is replacements_made / total_words, where
+            total_words counts every token (including stopwords). 'mappable_words'
+            is replacements_made plus the words the matcher left unchanged
+            although they map to a different canonical (kept by never_replace,
+            domain or other filters), and 'content_replacement_rate' is
+            replacements_made / mappable_words: how thoroughly the mappable
+            vocabulary was applied (1.0 when nothing was held back).
+        """
+        out = io.StringIO()
+        statistics = self.process_text_into(text, out, preserve_case)
+        return out.getvalue(), statistics
+
+    def process_text_into(self, text: str, out: io.StringIO, preserve_case: bool = True) -> Dict:
+        """
+        Apply CVC transformation to text, writing the result into a buffer.
+
+        The buffer is cleared first, so one buffer can be reused across many
+        calls instead of building a new string for each.
+
+        Args:
+            text: Input text to process
+            out: Buffer to write the processed text to
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Statistics as returned by process_text
+        """
+        out.seek(0)
+        out.truncate()
+
+        if self.tokenizer == Tokenizer.UNICODE:
+            processed_text, statistics = self._process_text_unicode(text, preserve_case)
+            out.write(processed_text)
+        else:
+            processed_words, statistics = self._process_tokens(text, self._tokenize(text), preserve_case)
+            out.write(self.token_separator.join(processed_words))
+
+        _logger.debug("process_text: %d of %d words replaced",
+                      statistics['replacements_made'], statistics['total_words'])
+        return statistics
+
+    def process_words(self, words: List[str], preserve_case: bool = True) -> Tuple[List[str], Dict]:
+        """
+        Apply CVC transformation to already-tokenized words.
+
+        Words are used as given, without re-splitting. Replacement 'start'/'end'
+        indices refer to ' '.join(words).
+
+        Args:
+            words: Tokens to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Tuple of (processed_words, statistics). A matched multi-word
+            synonym becomes a single token, so the lists may differ in length.
+        """
+        tokens = []
+        offset = 0
+        for word in words:
+            tokens.append((word, offset, offset + len(word)))
+            offset += len(word) + 1
+
+        return self._process_tokens(' '.join(words), tokens, preserve_case)
+
+    def _process_tokens(self, text: str, tokens: List[Tuple[str, int, int]],
+                        preserve_case: bool) -> Tuple[List[str], Dict]:
+        """Replace mapped words in tokens, which carry their start/end offsets in text."""
+        processed_words = []
+        replacements = []
+        held = 0
+        for _, _, processed_word, token_replacements, kept in \
+                self._iter_matches(text, tokens, preserve_case):
+            processed_words.append(processed_word)
+            replacements.extend(token_replacements)
+            held += kept
+
+        return processed_words, self._make_statistics(self._count_words(tokens), replacements, held)
+
+    def _iter_matches(self, text: str, tokens: List[Tuple[str, int, int]],
+                      preserve_case: bool) -> Iterator[Tuple[int, int, str, List[Dict], int]]:
+        """
+        Walk tokens, yielding each processed word as it is decided.
+
+        Yields:
+            Tuple of (index, length, processed_word, replacements, held): the
+            first token index, how many tokens the word consumed (more than one
+            for a multi-word synonym), its processed form, the replacements
+            made, and 1 if a filter kept a word that maps to a different
+            canonical (else 0)
+        """
+        words = [token for token, _, _ in tokens]
+        # Held-back words are only possible, and only looked up, under a filter
+        filtered = self._filters_active()
+        candidates = self._candidate_starts(text) if self._automaton_applies() else None
+
+        i = 0
+        while i < len(words):
+            if self.skip_urls and _URL_LIKE_PATTERN.search(words[i]):
+                parts = self._extract_word_parts(words[i])
+                yield i, 1, words[i], [], int(bool(parts) and self._held_back(parts[1]))
+                i += 1
+                continue
+
+            # Tokens the automaton ruled out cannot start a match
+            if candidates is not None and tokens[i][1] not in candidates \
+                    and not (self.split_compounds and self._extract_word_parts(words[i]) is None) \
+                    and not (self.unicode_normalization
+                             and not unicodedata.is_normalized(self.unicode_normalization, words[i])):
+                yield i, 1, words[i], [], 0
+                i += 1
+                continue
+
+            if self.skip_midsentence_capitalized and i > 0:
+                parts = self._extract_word_parts(words[i])
+                if parts and self._keep_capitalized(
+                        parts[1], text[tokens[i - 1][1]:tokens[i][1] + len(parts[0])]):
+                    yield i, 1, words[i], [], int(self._held_back(parts[1]))
+                    i += 1
+                    continue
+
+            # Multi-word keys take priority over single words, even canonical
+            # phrases that are kept as they are
+            phrase = self._match_phrase(words, i) if self.max_phrase_len > 1 else None
+            if phrase and phrase[4].lower() == phrase[2].lower():
+                yield i, phrase[0], self.token_separator.join(words[i:i + phrase[0]]), [], 0
+                i += phrase[0]
+                continue
+            if phrase:
+                length, prefix, original, suffix, canonical = phrase
+                canonical = self._apply_case(original, canonical, preserve_case)
+
+                yield i, length, f"{prefix}{canonical}{suffix}", [{
+                    'position': i,
+                    'original': original,
+                    'canonical': canonical,
+                    'start': tokens[i][1] + len(prefix),
+                    'end': tokens[i + length - 1][2] - len(suffix)
+                }], 0
+                i += length
+                continue
+
+            word = words[i]
+
+            # Extract word without punctuation
+            parts = self._extract_word_parts(word)
+            if not parts:
+                compound = self._process_compound(word, tokens[i][1], i, preserve_case) \
+                    if self.split_compounds else None
+                if compound:
+                    yield i, 1, compound[0], compound[1], 0
+                else:
+                    yield i, 1, word, [], 0
+                i += 1
+                continue
+
+            prefix, core_word, suffix = parts
+
+            # Check for canonical mapping (a canonical mapping to itself is a no-op)
+            canonical = self._get_canonical(core_word)
+
+            if canonical and canonical.lower() != core_word.lower():
+                # Preserve original capitalization pattern
+                canonical = self._apply_case(core_word, canonical, preserve_case)
+
+                yield i, 1, f"{prefix}{canonical}{suffix}", [self._mark_fuzzy({
+                    'position': i,
+                    'original': core_word,
+                    'canonical': canonical,
+                    'start': tokens[i][1] + len(prefix),
+                    'end': tokens[i][2] - len(suffix)
+                })], 0
+            else:
+                held = canonical is None and filtered and self._held_back(core_word)
+                yield i, 1, word, [], int(held)
+            i += 1
+
+    def tokens(self, text: str, preserve_case: bool = True) -> Iterator['Token']:
+        """
+        Walk text token by token, classifying each as kept or replaced.
+
+        Whitespace is skipped. Punctuation around a replaced word, and the
+        unchanged parts of a split compound, come out as KeptToken pieces, so
+        the yielded spans cover every non-whitespace character in order.
+
+        Args:
+            text: Input text to walk
+            preserve_case: Whether to preserve original capitalization
+
+        Yields:
+            KeptToken or ReplacedToken, with 'start'/'end' indices into text
+        """
+        tokens = self._tokenize(text)
+        if self.tokenizer == Tokenizer.UNICODE:
+            # The unicode path has no incremental form; walk its replacements
+            _, stats = self._process_text_unicode(text, preserve_case)
+            matches = [(0, len(tokens), None, stats['replacements'], 0)] if tokens else []
+        else:
+            matches = self._iter_matches(text, tokens, preserve_case)
+
+        for i, length, _, replacements, _ in matches:
+            position = tokens[i][1]
+            for r in sorted(replacements, key=lambda r: r['start']):
+                yield from _kept_tokens(text, position, r['start'])
+                yield ReplacedToken(r['original'], r['canonical'], r['start'], r['end'])
+                position = r['end']
+            yield from _kept_tokens(text, position, tokens[i + length - 1][2])
+
+    def _process_text_unicode(self, text: str, preserve_case: bool) -> Tuple[str, Dict]:
+        """
+        process_text for Tokenizer.UNICODE.
+
+        Words are runs of Unicode word characters, so punctuation need not be
+        surrounded by spaces. Everything between words is copied verbatim and
+        only words count toward total_words. In scripts written without spaces
+        (Han, Hiragana, Katakana) every character is a word; a key of several
+        such characters matches as a phrase of that many words, joined
+        without spaces, so max_phrase_len must cover it.
+        """
+        words = [(m.group(), m.start(), m.end()) for m in _UNICODE_WORD_PATTERN.finditer(text)]
+        pieces = []
+        replacements = []
+        held = 0
+        filtered = self._filters_active()
+        last_end = 0
+
+        # Words inside URL-like whitespace tokens are never matched
+        protected = set()
+        if self.skip_urls:
+            spans = [(start, end) for _, start, end in self._tokenize(text)
+                     if _URL_LIKE_PATTERN.search(text[start:end])]
+            protected = {
+                index for index, (_, start, _) in enumerate(words)
+                if any(a <= start < b for a, b in spans)
+            }
+
+        i = 0
+        while i < len(words):
+            length, canonical = 1, None
+            preceding = text[words[i - 1][1]:words[i][1]] if i else ''
+            # Longest phrase first; phrase words may only be separated by whitespace
+            sizes = range(min(self.max_phrase_len, len(words) - i), 0, -1) \
+                if not self._keep_capitalized(words[i][0], preceding) else ()
+            for size in sizes:
+                span = words[i:i + size]
+                if any(b[1] > a[2] and not text[a[2]:b[1]].isspace() for a, b in zip(span, span[1:])):
+                    continue
+                if protected and any(index in protected for index in range(i, i + size)):
+                    continue
+                original = _join_words(span)
+                candidate = self._get_canonical(original)
+                if candidate:
+                    length, canonical = size, candidate
+                    break
+
+            start, end = words[i][1], words[i + length - 1][2]
+            original = _join_words(words[i:i + length])
+            pieces.append(text[last_end:start])
+            # A canonical phrase matches too, but is kept as it is
+            if canonical and canonical.lower() != original.lower():
+                canonical = self._apply_case(original, canonical, preserve_case)
+                pieces.append(canonical)
+                replacements.append(self._mark_fuzzy({
+                    'position': i,
+                    'original': original,
+                    'canonical': canonical,
+                    'start': start,
+                    'end': end
+                }))
+            else:
+                pieces.append(text[start:end])
+                if canonical is None and (filtered or not sizes or i in protected):
+                    held += self._held_back(original)
+            last_end = end
+            i += length
+
+        pieces.append(text[last_end:])
+        processed_text = ''.join(pieces)
+
+        return processed_text, self._make_statistics(len(words), replacements, held)
+
+    def process_text_minimize(self, text: str, preserve_case: bool = True) -> Tuple[str, Dict]:
+        """
+        Replace each mapped word with the shortest form of its mapping.
+
+        Unlike process_text, the replacement is the shortest (in UTF-8 bytes)
+        of the canonical and its synonyms, so a canonical word can itself be
+        shortened. Words are only replaced when that saves bytes; ties favour
+        the canonical. Whitespace is kept as in text, and only single words
+        are considered.
+
+        Args:
+            text: Input text to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Tuple of (processed_text, statistics), with the statistics of
+            process_text plus 'bytes_saved'
+        """
+        tokens = self._tokenize(text)
+        replacements = []
+        by_domain = Counter()
+
+        for i, (token, start, end) in enumerate(tokens):
+            if self.skip_urls and _URL_LIKE_PATTERN.search(token):
+                continue
+            parts = self._extract_word_parts(token)
+            if not parts:
+                continue
+
+            prefix, word, suffix = parts
+            if i > 0 and self._keep_capitalized(word, text[tokens[i - 1][1]:start + len(prefix)]):
+                continue
+            canonical = self._get_canonical(word)
+            if canonical is None:
+                # Canonical words map to themselves but may still have a shorter synonym
+                info = self.canonical_lookup.get(word.lower())
+                if info is None or self._word_excluded(word) or not self._mapping_allowed(info):
+                    continue
+                canonical = info['canonical']
+
+            info = self.canonical_lookup.get(canonical.lower())
+            forms = [canonical] + (info['synonyms'] if info else [])
+            shortest = min(forms, key=_utf8_size)
+            if _utf8_size(shortest) >= _utf8_size(word):
+                continue
+
+            shortest = self._apply_case(word, shortest, preserve_case)
+            replacements.append({
+                'position': i,
+                'original': word,
+                'canonical': shortest,
+                'start': start + len(prefix),
+                'end': end - len(suffix)
+            })
+            by_domain[info.get('domain', 'unknown') if info else 'unknown'] += 1
+
+        processed_text = _apply_replacements(text, replacements)
+        statistics = self._make_statistics(self._count_words(tokens), replacements)
+        statistics['by_domain'] = dict(by_domain)
+        statistics['bytes_saved'] = _utf8_size(text) - _utf8_size(processed_text)
+
+        return processed_text, statistics
+
+    def process_text_json(self, text: str, preserve_case: bool = True) -> str:
+        """
+        Apply CVC transformation and serialize the result as JSON.
+
+        Args:
+            text: Input text to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            JSON object string of the form {"text": ..., "stats": {...}}, with
+            stats as returned by process_text
+        """
+        processed_text, stats = self.process_text(text, preserve_case)
+        return json.dumps({'text': processed_text, 'stats': stats}, ensure_ascii=False)
+
+    def process_text_reversible(self, text: str,
+                                preserve_case: bool = True) -> Tuple[str, Dict, List[Dict]]:
+        """
+        Apply CVC transformation and record how to undo it.
+
+        Only replaced words change; whitespace is kept as in text, so
+        apply_undo(processed_text, undo_log) == text.
+
+        Args:
+            text: Input text to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Tuple of (processed_text, statistics, undo_log). Each undo record
+            holds the word 'position', the 'original' and 'canonical' words, and
+            the 'start'/'end' span of the canonical word in processed_text.
+        """
+        _, stats = self.process_text(text, preserve_case)
+
+        undo_log = []
+        shift = 0
+        for r in sorted(stats['replacements'], key=lambda r: r['start']):
+            start = r['start'] + shift
+            undo_log.append({
+                'position': r['position'],
+                'original': text[r['start']:r['end']],
+                'canonical': r['canonical'],
+                'start': start,
+                'end': start + len(r['canonical'])
+            })
+            shift += len(r['canonical']) - (r['end'] - r['start'])
+
+        return _apply_replacements(text, stats['replacements']), stats, undo_log
+
+    def process_text_annotated(self, text: str, preserve_case: bool = True) -> str:
+        """
+        Preview CVC transformation by marking each replacement inline.
+
+        Replaced words are shown as [original→canonical]; everything else,
+        whitespace included, is left exactly as in text.
+
+        Args:
+            text: Input text to preview
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Annotated text
+        """
+        _, stats = self.process_text(text, preserve_case)
+        annotations = [
+            {**r, 'canonical': f"[{text[r['start']:r['end']]}\u2192{r['canonical']}]"}
+            for r in stats['replacements']
+        ]
+        return _apply_replacements(text, annotations)
+
+    def _count_words(self, tokens: List[Tuple[str, int, int]]) -> int:
+        """Count tokens toward total_words, honoring count_only_word_tokens."""
+        if not self.count_only_word_tokens:
+            return len(tokens)
+        return sum(1 for token, _, _ in tokens if _VOCAB_WORD_PATTERN.search(token))
+
+    def _filters_active(self) -> bool:
+        """Whether a word-level, domain or frequency filter can keep a mapped word."""
+        return bool(self.never_replace or self.min_word_len or self.skip_all_caps
+                    or self.active_domains is not None or self.min_frequency_rank is not None
+                    or self.max_frequency_rank is not None)
+
+    def _held_back(self, word: str) -> bool:
+        """Whether a word left unchanged maps to a different canonical, ignoring filters."""
+        canonical = self._lookup_canonical(word)
+        return canonical is not None and canonical.lower() != word.lower()
+
+    def _make_statistics(self, total_words: int, replacements: List[Dict], held: int = 0) -> Dict:
+        """
+        Build process_text statistics, including the per-domain breakdown.
+
+        held is the number of words kept unchanged by filters although they
+        map to a different canonical (see process_text's 'mappable_words').
+        """
+        by_domain = Counter()
+        for replacement in replacements:
+            info = self.canonical_lookup.get(replacement['canonical'].lower())
+            by_domain[info.get('domain', 'unknown') if info else 'unknown'] += 1
+
+        return {
+            'total_words': total_words,
+            'replacements_made': len(replacements),
+            'replacement_rate': len(replacements) / total_words if total_words else 0,
+            'mappable_words': len(replacements) + held,
+            'content_replacement_rate':
+                len(replacements) / (len(replacements) + held) if replacements else 0,
+            'replacements': replacements,
+            'by_domain': dict(by_domain)
+        }
+
+    def process_markdown(self, text: str, preserve_case: bool = True) -> Tuple[str, Dict]:
+        """
+        Apply CVC transformation to the prose of a Markdown document.
+
+        Code blocks (fenced or indented), inline code, link and image URLs,
+        autolinks and link reference definitions are left untouched, and all
+        whitespace is kept as in text.
+
+        Args:
+            text: Markdown document to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Tuple of (processed_text, statistics) counting only prose words.
+            Replacement 'start'/'end' indices refer to text.
+        """
+        return self._process_segments(_markdown_segments(text), preserve_case)
+
+    def process_html(self, html: str, preserve_case: bool = True) -> Tuple[str, Dict]:
+        """
+        Apply CVC transformation to the text nodes of an HTML document.
+
+        Tags and attributes, comments, CDATA sections, <script>/<style>
+        contents and character references such as &amp; are left untouched.
+
+        Args:
+            html: HTML document to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Tuple of (processed_html, statistics) counting only text-node words.
+            Replacement 'start'/'end' indices refer to html.
+        """
+        return self._process_segments(_split_literals(html, _HTML_LITERAL_PATTERN), preserve_case)
+
+    def batch_process(self, texts: Iterable[str],
+                      preserve_case: bool = True) -> List[Tuple[str, Dict]]:
+        """
+        Apply CVC transformation to many texts in one call.
+
+        Suited to large collections of short strings, e.g.
+        processor.batch_process(df['text']) instead of a per-row apply.
+
+        Args:
+            texts: Texts to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            List of (processed_text, statistics) tuples in input order
+        """
+        return [self.process_text(text, preserve_case) for text in texts]
+
+    def process_by_sentence(self, text: str, preserve_case: bool = True) -> List[Tuple[str, Dict]]:
+        """
+        Split text into sentences and process each one independently.
+
+        Args:
+            text: Input text to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            List of (processed_sentence, statistics) tuples, one per sentence
+            found by split_sentences
+        """
+        return [self.process_text(sentence, preserve_case) for sentence in split_sentences(text)]
+
+    def _process_segments(self, segments: Iterable[Tuple[str, bool]],
+                          preserve_case: bool) -> Tuple[str, Dict]:
+        """Process the prose pieces of a document split into (segment, is_prose) pieces."""
+        pieces = []
+        replacements = []
+        total_words = 0
+        held = 0
+        offset = 0
+
+        for segment, is_prose in segments:
+            if is_prose:
+                _, stats = self.process_text(segment, preserve_case)
+                held += stats['mappable_words'] - stats['replacements_made']
+                pieces.append(_apply_replacements(segment, stats['replacements']))
+                for r in stats['replacements']:
+                    replacements.append({
+                        **r,
+                        'position': r['position'] + total_words,
+                        'start': r['start'] + offset,
+                        'end': r['end'] + offset
+                    })
+                total_words += stats['total_words']
+            else:
+                pieces.append(segment)
+            offset += len(segment)
+
+        return ''.join(pieces), self._make_statistics(total_words, replacements, held)
+
+    def process_text_lazy(self, text: str, preserve_case: bool = True) -> str:
+        """
+        Apply CVC transformation, returning the input untouched if nothing changes.
+
+        Unlike process_text, no statistics or output words are built: matches
+        are collected as tokens are scanned, and text without any is returned
+        as the same string object. Otherwise only the replaced words are
+        substituted, so whitespace is kept as in text (as process_file does)
+        rather than rejoined with token_separator.
+
+        Args:
+            text: Input text to process
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            Processed text, or text itself when no replacements were made
+        """
+        # Without any automaton hit nothing can match, unless a token is one
+        # _iter_matches looks up regardless (compounds, unnormalized words)
+        if self._automaton_applies() and not self.split_compounds \
+                and not (self.unicode_normalization
+                         and not unicodedata.is_normalized(self.unicode_normalization, text)) \
+                and not self._compiled_automaton().search(text):
+            return text
+
+        replacements = list(self._scan_matches(text, preserve_case))
+        return _apply_replacements(text, replacements) if replacements else text
+
+    def expand_text(self, text: str,
+                    strategy: ExpandStrategy = ExpandStrategy.FIRST_SYNONYM,
+                    preserve_case: bool = True,
+                    rng: Optional[random.Random] = None) -> Tuple[str, Dict]:
+        """
+        Rewrite canonical words back to one of their synonyms.
+
+        Args:
+            text: Input text to expand
+            strategy: How to choose among a canonical word's synonyms
+            preserve_case: Whether to preserve original capitalization
+            rng: Random source for ExpandStrategy.RANDOM (defaults to module random)
+
+        Returns:
+            Tuple of (expanded_text, statistics)
+        """
+        tokens = self._tokenize(text)
+        words = [token for token, _, _ in tokens]
+        expanded_words = []
+        replacements = []
+
+        for i, (word, start, end) in enumerate(tokens):
+            parts = self._extract_word_parts(word)
+            if not parts:
+                expanded_words.append(word)
+                continue
+
+            prefix, core_word, suffix = parts
+            synonym = self._choose_synonym(core_word, strategy, rng)
+
+            if synonym:
+                synonym = self._apply_case(core_word, synonym, preserve_case)
+
+                expanded_words.append(f"{prefix}{synonym}{suffix}")
+                replacements.append({
+                    'position': i,
+                    'original': core_word,
+                    'expanded': synonym,
+                    'start': start + len(prefix),
+                    'end': end - len(suffix)
+                })
+            else:
+                expanded_words.append(word)
+
+        expanded_text = self.token_separator.join(expanded_words)
+
+        statistics = {
+            'total_words': len(words),
+            'replacements_made': len(replacements),
+            'replacement_rate': len(replacements) / len(words) if words else 0,
+            'replacements': replacements
+        }
+
+        return expanded_text, statistics
+
+    def augment(self, text: str, n: int, seed: int = 0,
+                preserve_case: bool = True) -> List[str]:
+        """
+        Generate randomized variants of canonical text for data augmentation.
+
+        Each variant is expand_text with ExpandStrategy.RANDOM; words without
+        synonyms stay as they are. The same seed always gives the same variants.
+
+        Args:
+            text: Canonical text to vary
+            n: Number of variants to generate
+            seed: Seed for the random number generator
+            preserve_case: Whether to preserve original capitalization
+
+        Returns:
+            List of n variant texts
+        """
+        rng = random.Random(seed)
+        return [
+            self.expand_text(text, ExpandStrategy.RANDOM, preserve_case, rng)[0]
+            for _ in range(n)
+        ]
+
+    def _choose_synonym(self, word: str, strategy: ExpandStrategy,
+                        rng: Optional[random.Random] = None) -> Optional[str]:
+        """Pick a synonym for a canonical word according to strategy."""
+        if word.lower() in self.never_replace:
+            return None
+
+        info = self.canonical_lookup.get(word.lower())
+        if not info or not info['synonyms'] or not self._mapping_allowed(info):
+            return None
+
+        synonyms = info['synonyms']
+        if strategy == ExpandStrategy.LONGEST_SYNONYM:
+            return max(synonyms, key=len)
+        if strategy == ExpandStrategy.RANDOM:
+            return (rng or random).choice(synonyms)
+        return synonyms[0]
+
+    def _process_compound(self, word: str, start: int, position: int,
+                          preserve_case: bool) -> Optional[Tuple[str, List[Dict]]]:
+        """
+        Process a token with internal hyphens or apostrophes.
+
+        The whole compound is looked up first (e.g. a mapped "well-known").
+        Otherwise each hyphen-separated segment is looked up on its own, so
+        "large-scale" becomes "big-scale". A segment with an apostrophe is
+        only matched whole, except that a trailing "'s" is split off so
+        "it's" and "large's" look up "it" and "large".
+
+        Returns:
+            Tuple of (processed_token, replacements), or None if not a compound
+        """
+        match = re.match(r"^([^\w]*)(\w+(?:[-'\u2019]\w+)+)([^\w]*)$", word)
+        if not match:
+            return None
+
+        prefix, core, suffix = match.groups()
+        offset = start + len(prefix)
+
+        def replace(original: str, at: int) -> Optional[Tuple[str, Dict]]:
+            canonical = self._get_canonical(original)
+            if not canonical or canonical.lower() == original.lower():
+                return None
+            canonical = self._apply_case(original, canonical, preserve_case)
+            return canonical, self._mark_fuzzy({
+                'position': position,
+                'original': original,
+                'canonical': canonical,
+                'start': at,
+                'end': at + len(original)
+            })
+
+        whole = replace(core, offset)
+        if whole:
+            return f"{prefix}{whole[0]}{suffix}", [whole[1]]
+
+        pieces = []
+        replacements = []
+        for segment in re.split(r'(-)', core):
+            stem, tail = segment, ''
+            possessive = re.match(r"^(\w+)(['\u2019]s)$", segment)
+            if possessive:
+                stem, tail = possessive.groups()
+
+            result = replace(stem, offset) if re.fullmatch(r'\w+', stem) else None
+            if result:
+                pieces.append(result[0] + tail)
+                replacements.append(result[1])
+            else:
+                pieces.append(segment)
+            offset += len(segment)
+
+        return f"{prefix}{''.join(pieces)}{suffix}", replacements
+
+    def _automaton_applies(self) -> bool:
+        """Whether use_automaton can prefilter whitespace tokens under the current options."""
+        # Accent folding, fuzzy matching and split-off digits can match tokens
+        # the automaton's literal keys miss
+        return self.use_automaton and self.tokenizer == Tokenizer.WHITESPACE \
+            and not (self.accent_insensitive or self.fuzzy_distance or self.split_trailing_digits)
+
+    def _compiled_automaton(self) -> re.Pattern:
+        """Pattern matching at each whitespace token where some mapping key may match."""
+        if self._automaton is None:
+            pattern = build_trie_pattern(self.case_insensitive_lookup)
+            # Zero-width so that overlapping candidates are all reported
+            self._automaton = re.compile(
+                rf'(?<!\S)(?=[^\w\s]*(?:{pattern})[^\w\s]*(?!\S))', re.IGNORECASE
+            )
+        return self._automaton
+
+    def _candidate_starts(self, text: str) -> Set[int]:
+        """Offsets of whitespace tokens where some mapping key may match."""
+        return {m.start() for m in self._compiled_automaton().finditer(text)}
+
+    def _tokenize(self, text: str) -> List[Tuple[str, int, int]]:
+        """Split text on whitespace, keeping each token's (start, end) indices."""
+        return [(m.group(), m.start(), m.end()) for m in _TOKEN_PATTERN.finditer(text)]
+
+    def _match_phrase(self, words: List[str], start: int) -> Optional[Tuple[int, str, str, str, str]]:
+        """
+        Find the longest multi-word mapping key starting at words[start].
+
+        Only the first word may carry leading punctuation and only the last
+        may carry trailing punctuation; interior words must be bare.
+
+        Returns:
+            Tuple of (length, prefix, phrase, suffix, canonical), or None. The
+            canonical equals the phrase when the phrase is a canonical itself
+        """
+        longest = min(self.max_phrase_len, len(words) - start)
+        for length in range(longest, 1, -1):
+            parts = [self._extract_word_parts(w) for w in words[start:start + length]]
+            if not all(parts):
+                continue
+            if any(p[2] for p in parts[:-1]) or any(p[0] for p in parts[1:]):
+                continue
+
+            phrase = ' '.join(p[1] for p in parts)
+            canonical = self._get_canonical(phrase)
+            if canonical:
+                return length, parts[0][0], phrase, parts[-1][2], canonical
+
+        return None
+
+    def _extract_word_parts(self, word: str) -> Optional[Tuple[str, str, str]]:
+        """Split a token into (prefix, core_word, suffix) around its word core."""
+        match = _WORD_PARTS_PATTERN.match(word)
+        if not match:
+            return None
+        if self.split_trailing_digits:
+            prefix, core_word, suffix = match.groups()
+            digits = _TRAILING_DIGITS_PATTERN.search(core_word)
+            if digits:
+                return prefix, core_word[:digits.start()], core_word[digits.start():] + suffix
+        return match.groups()
+
+    def validate(self) -> List[ValidationError]:
+        """
+        Check the loaded mapping data for internal consistency.
+
+        Returns:
+            List of problems found (empty if the mappings are consistent)
+        """
+        errors = []
+        canonicals = {info['canonical'] for info in self.mappings.values()}
+
+        for synonym, canonical in self.reverse_lookup.items():
+            if canonical not in canonicals:
+                errors.append(ValidationError(
+                    'unknown_canonical',
+                    f"'{synonym}' maps to '{canonical}', which is not a mapping canonical"
+                ))
+
+        errors.extend(find_conflicting_synonyms(self.mappings))
+
+        total_mappings = self.metadata.get('total_mappings')
+        if total_mappings is not None and total_mappings != len(self.mappings):
+            errors.append(ValidationError(
+                'total_mappings_mismatch',
+                f"metadata.total_mappings is {total_mappings} but there are {len(self.mappings)} mappings"
+            ))
+
+        total_synonyms = self.metadata.get('total_synonyms')
+        actual_synonyms = self.synonym_count()
+        if total_synonyms is not None and total_synonyms != actual_synonyms:
+            errors.append(ValidationError(
+                'total_synonyms_mismatch',
+                f"metadata.total_synonyms is {total_synonyms} but there are {actual_synonyms} synonyms"
+            ))
+
+        for path in self.detect_cycles():
+            if path[0].lower() == path[-1].lower():
+                errors.append(ValidationError(
+                    'mapping_cycle', f"cyclic mapping: {' -> '.join(path)}"
+                ))
+            else:
+                errors.append(ValidationError(
+                    'mapping_chain', f"chained mapping: {' -> '.join(path)}"
+                ))
+
+        return errors
+
+    def detect_cycles(self) -> List[List[str]]:
+        """
+        Find cycles and multi-hop chains in reverse_lookup.
+
+        Treats each synonym -> canonical entry as a graph edge (ignoring
+        canonicals mapped to themselves). A cycle is reported once, starting
+        and ending at the same word; a chain is reported from its first word.
+
+        Returns:
+            List of word paths, e.g. ['a', 'b', 'c'] or ['a', 'b', 'a']
+        """
+        def next_word(word: str) -> Optional[str]:
+            target = self._lookup_canonical(word)
+            if target is None or target.lower() == word.lower():
+                return None
+            return target
+
+        targets = set()
+        for synonym in self.reverse_lookup:
+            target = next_word(synonym)
+            if target is not None:
+                targets.add(target.lower())
+
+        found = []
+        seen_cycles = set()
+        for start in self.reverse_lookup:
+            if next_word(start) is None:
+                continue
+
+            path = [start]
+            visited = {start.lower()}
+            is_cycle = False
+            current = start
+            while True:
+                target = next_word(current)
+                if target is None:
+                    break
+                path.append(target)
+                if target.lower() in visited:
+                    is_cycle = True
+                    break
+                visited.add(target.lower())
+                current = target
+
+            if is_cycle:
+                # Report each cycle once, however many paths lead into it
+                cycle_start = [w.lower() for w in path].index(path[-1].lower())
+                cycle = path[cycle_start:-1]
+                key = frozenset(w.lower() for w in cycle)
+                if key not in seen_cycles:
+                    seen_cycles.add(key)
+                    found.append(cycle + [cycle[0]])
+            elif len(path) > 2 and start.lower() not in targets:
+                found.append(path)
+
+        return found
+
+    @property
+    def version(self) -> Optional[str]:
+        """Version string from the mapping metadata, if any."""
+        return self.metadata.get('version')
+
+    @property
+    def description(self) -> Optional[str]:
+        """Description from the mapping metadata, if any."""
+        return self.metadata.get('description')
+
+    @property
+    def total_mappings(self) -> int:
+        """Number of canonical mapping entries currently loaded."""
+        return len(self.mappings)
+
+    def list_domains(self) -> List[str]:
+        """
+        List the domains used by the loaded mappings.
+
+        Returns:
+            Sorted list of distinct domain names
+        """
+        return sorted(self.domains())
+
+    def domains(self) -> Dict[str, int]:
+        """
+        Count the loaded mappings per domain.
+
+        Returns:
+            Dictionary from each domain name to its number of mappings,
+            sorted by name
+        """
+        counts = Counter(info.get('domain', 'unknown') for info in self.mappings.values())
+        return dict(sorted(counts.items()))
+
+    def iter_mappings(self) -> Iterator[Tuple[str, Dict]]:
+        """
+        Iterate over the loaded mappings without copying them.
+
+        The entries are the processor's own and must not be modified; use
+        add_mapping and remove_mapping instead.
+
+        Yields:
+            (canonical, mapping info) pairs in mapping file order
+        """
+        for info in self.mappings.values():
+            yield info['canonical'], info
+
+    def __len__(self) -> int:
+        """Number of canonical mapping entries."""
+        return len(self.mappings)
+
+    def mapping_count(self) -> int:
+        """Number of canonical mapping entries, counted from the loaded data."""
+        return len(self.mappings)
+
+    def synonym_count(self) -> int:
+        """Number of synonyms across all mappings, counted from the loaded data."""
+        return sum(len(info['synonyms']) for info in self.mappings.values())
+
+    def canonical_for(self, word: str) -> Optional[str]:
+        """
+        Look up the canonical form a word would be replaced with.
+
+        Args:
+            word: Word to look up (without surrounding punctuation)
+
+        Returns:
+            Canonical form, or None if the word would be left unchanged
+        """
+        canonical = self._get_canonical(word)
+        # A canonical maps to itself in a derived lookup, but is never replaced
+        if canonical is None or canonical.lower() == word.lower():
+            return None
+        return canonical
+
+    def synonyms_for(self, canonical: str) -> Optional[List[str]]:
+        """
+        Look up the synonyms that map to a canonical word.
+
+        Args:
+            canonical: Canonical word to look up
+
+        Returns:
+            List of synonyms, or None if the word is not a canonical form
+        """
+        info = self.canonical_lookup.get(canonical.lower())
+        if not info or not self._mapping_allowed(info):
+            return None
+        return info['synonyms']
+
+    def contains(self, word: str) -> bool:
+        """
+        Check whether a word has a canonical mapping.
+
+        Args:
+            word: Word to check (without surrounding punctuation)
+
+        Returns:
+            True if process_text would replace the word
+        """
+        return self.canonical_for(word) is not None
+
+    def matched_vocabulary(self, text: str) -> Set[str]:
+        """
+        Find the mapped synonyms present in a text without processing it.
+
+        Args:
+            text: Text to scan
+
+        Returns:
+            Set of distinct words and phrases (as written in the text, without
+            surrounding punctuation) that process_text would replace with the
+            processor's current options (tokenizer, split_compounds, filters)
+        """
+        return {replacement['original'] for replacement in self._scan_matches(text)}
+
+    def detect_domain(self, text: str) -> List[Tuple[str, int]]:
+        """
+        Guess the domains a text belongs to from the mapped words it contains.
+
+        Args:
+            text: Text to scan
+
+        Returns:
+            List of (domain, count) pairs, where count is how many of
+            process_text's replacements the domain would account for, most
+            frequent first (ties sorted by domain name)
+        """
+        counts = Counter()
+        for replacement in self._scan_matches(text):
+            info = self.canonical_lookup.get(replacement['canonical'].lower())
+            counts[info.get('domain', 'unknown') if info else 'unknown'] += 1
+
+        return sorted(counts.items(), key=lambda item: (-item[1], item[0]))
+
+    def _scan_matches(self, text: str, preserve_case: bool = True) -> Iterator[Dict]:
+        """Yield the replacement record of each word or phrase process_text would replace."""
+        if self.tokenizer == Tokenizer.UNICODE:
+            yield from self._process_text_unicode(text, preserve_case)[1]['replacements']
+            return
+
+        for _, _, _, replacements, _ in self._iter_matches(text, self._tokenize(text), preserve_case):
+            yield from replacements
+
+    def lookup_ambiguities(self) -> Dict[str, Dict[str, str]]:
+        """
+        Find mapped keys that collide only under case folding.
+
+        A word matching one of the keys exactly uses that key's canonical.
+        Otherwise it matches case-insensitively, using the all-lowercase key
+        if there is one and else the key sorting first.
+
+        Returns:
+            Dictionary from each lowercased form shared by several keys to
+            those keys and their canonicals, sorted by form
+        """
+        groups = defaultdict(dict)
+        for key, canonical in self.reverse_lookup.items():
+            groups[key.lower()][key] = canonical
+
+        return {
+            folded: dict(sorted(keys.items()))
+            for folded, keys in sorted(groups.items()) if len(keys) > 1
+        }
+
+    def _get_canonical(self, word: str) -> Optional[str]:
+        """Get canonical form for a word, honoring the active filters."""
+        debug = _lookup_logger.isEnabledFor(logging.DEBUG)
+        if self._word_excluded(word):
+            if debug:
+                _lookup_logger.debug("%r: skipped by never_replace/min_word_len/skip_all_caps", word)
+            return None
+
+        canonical = self._lookup_canonical(word)
+        if canonical is None and self.fuzzy_distance and ' ' not in word:
+            canonical = self._lookup_fuzzy(word)
+        if canonical is None:
+            if debug:
+                _lookup_logger.debug("%r: no mapping", word)
+            return None
+
+        if self.resolve_chains:
+            canonical = self._follow_chain(word, canonical)
+
+        if (self.active_domains is not None or self.min_frequency_rank is not None
+                or self.max_frequency_rank is not None) \
+                and not self._mapping_allowed(self.canonical_lookup.get(canonical.lower())):
+            if debug:
+                _lookup_logger.debug("%r -> %r: skipped by domain/frequency filters", word, canonical)
+            return None
+
+        if debug:
+            _lookup_logger.debug("%r -> %r (%s match)", word, canonical, self._match_kind(word))
+        return canonical
+
+    def _match_kind(self, word: str) -> str:
+        """Name the lookup step that matched a mapped word, for debug logging."""
+        if word in self.reverse_lookup:
+            return 'exact'
+        if not self.case_sensitive and word.lower() in self.case_insensitive_lookup:
+            return 'case-insensitive'
+        if self.unicode_normalization and self._lookup_normalized(word) is not None:
+            return 'normalized'
+        if self.accent_insensitive and fold_accents(word.lower()) in self.accent_insensitive_lookup:
+            return 'accent-insensitive'
+        if word.lower() in self.canonical_lookup:
+            return 'canonical word'
+        return 'fuzzy'
+
+    def _keep_capitalized(self, word: str, preceding: str) -> bool:
+        """
+        Whether skip_midsentence_capitalized keeps a word as a likely proper noun.
+
+        Args:
+            word: Word or phrase without surrounding punctuation
+            preceding: Text from the previous word up to this one ('' for the
+                first word), used to tell whether it starts a sentence
+        """
+        if not (self.skip_midsentence_capitalized and word.istitle() and preceding):
+            return False
+
+        match = _SENTENCE_BREAK_PATTERN.search(preceding)
+        if not match:
+            return True
+        period = match.group().startswith('.') and not match.group().startswith('...')
+        return period and '\n' not in match.group() and _ends_with_abbreviation(preceding[:match.start()])
+
+    def _word_excluded(self, word: str) -> bool:
+        """Whether the word-level filters keep a word from being replaced."""
+        if len(word) < self.min_word_len or (self.never_replace and word.lower() in self.never_replace):
+            return True
+
+        return self.skip_all_caps and len(word) > 1 and word.isupper()
+
+    def _lookup_canonical(self, word: str) -> Optional[str]:
+        """Look up the raw canonical form for a word."""
+        # Try exact match first
+        if word in self.reverse_lookup:
+            return self.reverse_lookup[word]
+
+        # Try case-insensitive match
+        if not self.case_sensitive:
+            canonical = self.case_insensitive_lookup.get(word.lower())
+            if canonical is not None:
+                return canonical
+
+        if self.unicode_normalization:
+            canonical = self._lookup_normalized(word)
+            if canonical is not None:
+                return canonical
+
+        # Try accent-insensitive match (always case-insensitive too)
+        if self.accent_insensitive:
+            return self.accent_insensitive_lookup.get(fold_accents(word.lower()))
+
+        return None
+
+    def _lookup_normalized(self, word: str) -> Optional[str]:
+        """Look up a word with it and all keys Unicode-normalized."""
+        form = self.unicode_normalization
+        if self._normalized_lookup is None or self._normalized_lookup[0] != form:
+            exact = {unicodedata.normalize(form, k): v for k, v in self.reverse_lookup.items()}
+            keys_changed = any(unicodedata.normalize(form, k) != k for k in self.reverse_lookup)
+            self._normalized_lookup = (form, exact, build_case_insensitive_lookup(exact), keys_changed)
+        _, exact, folded, keys_changed = self._normalized_lookup
+
+        # Nothing new can match an already-normalized word if no key changed
+        if not keys_changed and (word.isascii() or unicodedata.is_normalized(form, word)):
+            return None
+
+        normalized = unicodedata.normalize(form, word)
+        if normalized in exact:
+            return exact[normalized]
+        if not self.case_sensitive:
+            return folded.get(normalized.lower())
+        return None
+
+    def _lookup_fuzzy(self, word: str) -> Optional[str]:
+        """
+        Find the canonical of the closest single word within fuzzy_distance.
+
+        Candidates are the mapped synonyms and the canonical words themselves,
+        so a misspelled canonical is corrected too.
+        """
+        if len(word) < FUZZY_MIN_WORD_LENGTH:
+            return None
+
+        if self._fuzzy_index is None:
+            targets = dict(self.case_insensitive_lookup)
+            for key, info in self.canonical_lookup.items():
+                targets.setdefault(key, info['canonical'])
+
+            # Keys grouped by length so only similar-length keys are compared
+            by_length: Dict[int, List[str]] = {}
+            for key in sorted(targets):
+                if ' ' not in key:
+                    by_length.setdefault(len(key), []).append(key)
+            self._fuzzy_index = (targets, by_length, OrderedDict())
+        targets, by_length, cache = self._fuzzy_index
+
+        folded, limit = word.lower(), self.fuzzy_distance
+        if (folded, limit) in cache:
+            cache.move_to_end((folded, limit))
+            best_key = cache[folded, limit]
+        else:
+            best_key, best_distance = None, limit + 1
+            for length in range(len(folded) - limit, len(folded) + limit + 1):
+                for key in by_length.get(length, ()):
+                    distance = edit_distance(folded, key, best_distance - 1)
+                    if distance < best_distance:
+                        best_key, best_distance = key, distance
+            cache[folded, limit] = best_key
+            if len(cache) > FUZZY_CACHE_SIZE:
+                cache.popitem(last=False)
+
+        return targets[best_key] if best_key is not None else None
+
+    def _mark_fuzzy(self, replacement: Dict) -> Dict:
+        """Flag a replacement with 'fuzzy': True if it came from a fuzzy match."""
+        if self.fuzzy_distance and ' ' not in replacement['original'] \
+                and self._lookup_canonical(replacement['original']) is None:
+            replacement['fuzzy'] = True
+        return replacement
+
+    def _follow_chain(self, word: str, canonical: str) -> str:
+        """Follow chained mappings to a terminal canonical, stopping at cycles."""
+        seen = {word.lower(), canonical.lower()}
+        while True:
+            target = self._lookup_canonical(canonical)
+            if target is None or target.lower() in seen:
+                return canonical
+            seen.add(target.lower())
+            canonical = target
+
+    def _mapping_allowed(self, info: Optional[Dict]) -> bool:
+        """Check whether a mapping passes the processor's filters."""
+        if self.active_domains is not None:
+            if info is None or info.get('domain') not in self.active_domains:
+                return False
+
+        if self.min_frequency_rank is not None or self.max_frequency_rank is not None:
+            rank = info.get('frequency_rank') if info else None
+            if rank is None:
+                return False
+            if self.min_frequency_rank is not None and rank < self.min_frequency_rank:
+                return False
+            if self.max_frequency_rank is not None and rank > self.max_frequency_rank:
+                return False
+
+        return True
+
+    def _apply_case(self, original: str, canonical: str, preserve_case: bool) -> str:
+        """Apply the configured case strategy (or none, if preserve_case is off)."""
+        cased = self._cased_canonical(original, canonical, preserve_case)
+        return sys.intern(cased) if self.intern_canonicals else cased
+
+    def _cased_canonical(self, original: str, canonical: str, preserve_case: bool) -> str:
+        """Recase a canonical form after the original word (see _apply_case)."""
+        strategy = self.case_strategy if preserve_case else CaseStrategy.FORCE_CANONICAL
+
+        if strategy == CaseStrategy.PRESERVE:
+            return self._preserve_case(original, canonical)
+        if strategy == CaseStrategy.EXACT:
+            # Characters past the end of original follow its last character
+            return ''.join(
+                ch.upper() if original[min(i, len(original) - 1)].isupper() else ch.lower()
+                for i, ch in enumerate(canonical)
+            )
+        if strategy == CaseStrategy.MATCH_FIRST_LETTER:
+            first = canonical[0].upper() if original[0].isupper() else canonical[0].lower()
+            return first + canonical[1:]
+        if strategy == CaseStrategy.MATCH_ALL_CAPS:
+            return canonical.upper() if original.isupper() else canonical
+        if strategy == CaseStrategy.FORCE_LOWER:
+            return canonical.lower()
+        return canonical
+
+    def _preserve_case(self, original: str, canonical: str) -> str:
+        """Preserve the capitalization pattern of original word."""
+        if original.isupper():
+            return canonical.upper()
+        elif ' ' in original or not canonical.islower():
+            # Phrases and canonicals with capitals of their own (e.g. 'NYC')
+            # keep the canonical's stored casing
+            return canonical
+        elif original[0].isupper():
+            return canonical.capitalize()
+        else:
+            return canonical.lower()
+
+    def process_file(self, input_file: str, output_file: str,
+                     undo_log_file: Optional[str] = None,
+                     replacements_log_file: Optional[str] = None,
+                     append: bool = False) -> Dict:
+        """
+        Process an entire file with CVC transformation.
+
+        Only replaced words change: whitespace, including each line's
+        terminator (LF or CRLF), is kept, and a missing final newline stays
+        missing.
+
+        Args:
+            input_file: Path to input file
+            output_file: Path to output file; if it is input_file, the input is
+                replaced atomically once processing completes
+            undo_log_file: If given, write a JSONL undo log here so restore_file
+                can rebuild the input
+            replacements_log_file: If given, write one JSON object per
+                replacement here (see process_stream)
+            append: Add the processed text to the end of output_file instead
+                of overwriting it; not supported with undo_log_file, whose line
+                numbers would not match the appended output
+
+        Returns:
+            Dictionary of processing statistics (for this input only, when
+            appending)
+
+        Raises:
+            InputFileError: If input_file cannot be read or, unless
+                invalid_utf8 is set, is not valid UTF-8
+        """
+        return self.process_file_with_progress(input_file, output_file, None, undo_log_file,
+                                               replacements_log_file=replacements_log_file,
+                                               append=append)
+
+    def process_file_with_progress(self, input_file: str, output_file: str,
+                                   progress: Optional[Callable[[ProgressEvent], None]],
+                                   undo_log_file: Optional[str] = None,
+                                   interval: int = 10000,
+                                   replacements_log_file: Optional[str] = None,
+                                   append: bool = False) -> Dict:
+        """
+        Process an entire file, reporting progress as it goes.
+
+        Args:
+            input_file: Path to input file
+            output_file: Path to output file
+            progress: Called with a ProgressEvent every interval lines and once
+                at the end (None disables reporting)
+            undo_log_file: If given, write a JSONL undo log here (see process_file)
+            interval: Number of lines between progress reports
+            replacements_log_file: If given, write a JSONL replacements log
+                here (see process_stream)
+            append: Add to the end of output_file (see process_file)
+
+        Returns:
+            Dictionary of processing statistics
+
+        Raises:
+            InputFileError: See process_file
+        """
+        if append and undo_log_file:
+            raise ValueError("An undo log cannot be combined with append")
+        if _same_file(input_file, output_file):
+            # Opening the output would truncate the input before it is read
+            if append:
+                raise ValueError(f"Cannot append {input_file} to itself")
+            stats = _write_replacing(output_file, lambda temp_path: self.process_file_with_progress(
+                input_file, temp_path, progress, undo_log_file, interval, replacements_log_file))
+            return {**stats, 'output_file': output_file}
+
+        # newline='' passes CRLF through untranslated in both directions
+        _logger.debug("process_file: %s -> %s", input_file, output_file)
+        io_options = _text_io_options(self.invalid_utf8)
+        with _open_input(input_file, newline='', **io_options) as fin, \
+                open(output_file, 'a' if append else 'w', newline='', **io_options) as fout, \
+                (open(undo_log_file, 'w') if undo_log_file else nullcontext()) as undo_log, \
+                (open(replacements_log_file, 'w') if replacements_log_file else nullcontext()) as log:
+            callback = None
+            if progress is not None:
+                total_bytes = os.fstat(fin.fileno()).st_size
+
+                def callback(event: ProgressEvent):
+                    progress(ProgressEvent(event.lines_processed, event.bytes_processed, total_bytes))
+
+            stats = self.process_stream(fin, fout, undo_log, callback, interval, log)
+        _logger.debug("process_file: %s done, %d lines, %d of %d words replaced", input_file,
+                      stats['total_lines'], stats['total_replacements'], stats['total_words'])
+
+        return {
+            'input_file': input_file,
+            'output_file': output_file,
+            **stats
+        }
+
+    def process_directory(self, input_dir: str, output_dir: str,
+                          pattern: str = '*.txt') -> Dict:
+        """
+        Process every matching file under a directory tree.
+
+        The directory structure is mirrored under output_dir. Binary files
+        and, unless invalid_utf8 is set, files that are not valid UTF-8 are
+        skipped rather than aborting the run, and any partial output for them
+        is removed. output_dir may be input_dir itself, in which case each
+        file is replaced atomically once processed (a skipped file is left as
+        it was). If output_dir is inside input_dir, files under it are not
+        treated as input.
+
+        Args:
+            input_dir: Directory searched recursively for input files
+            output_dir: Directory to write processed files to
+            pattern: Glob pattern selecting input file names
+
+        Returns:
+            Dictionary of statistics summed over all processed files, plus
+            'files' (per-file statistics) and 'skipped' (dicts with 'file'
+            and 'reason'). Entries in 'errors' also carry their 'file'
+        """
+        totals = _FileStatsAccumulator()
+        files = []
+        skipped = []
+        io_options = {'encoding': 'utf-8', **_text_io_options(self.invalid_utf8)}
+        output_root = Path(output_dir).resolve()
+        in_place = output_root == Path(input_dir).resolve()
+
+        for source in sorted(Path(input_dir).rglob(pattern)):
+            if not source.is_file():
+                continue
+            # Output of an earlier run under input_dir is not input
+            if not in_place and output_root in source.resolve().parents:
+                continue
+            target = Path(output_dir) / source.relative_to(input_dir)
+
+            with open(source, 'rb') as f:
+                if b'\0' in f.read(8192):
+                    skipped.append({'file': str(source), 'reason': 'binary file'})
+                    continue
+
+            def write(path) -> Dict:
+                with open(source, 'r', newline='', **io_options) as fin, \
+                        open(path, 'w', newline='', **io_options) as fout:
+                    return self.process_stream(fin, fout)
+
+            target.parent.mkdir(parents=True, exist_ok=True)
+            overwrites_source = _same_file(source, target)
+            try:
+                # Opening the target would truncate the source before it is read
+                stats = _write_replacing(str(target), write) if overwrites_source else write(target)
+            except UnicodeDecodeError as e:
+                if not overwrites_source:
+                    target.unlink(missing_ok=True)
+                skipped.append({'file': str(source), 'reason': f'not valid UTF-8 ({e.reason})'})
+                continue
+
+            totals.add_file({**stats, 'errors': [{'file': str(source), **e} for e in stats['errors']]})
+            files.append({'input_file': str(source), 'output_file': str(target), **stats})
+
+        return {**totals.result(), 'files': files, 'skipped': skipped}
+
+    def process_stream(self, reader: TextIO, writer: TextIO,
+                       undo_log: Optional[TextIO] = None,
+                       progress: Optional[Callable[[ProgressEvent], None]] = None,
+                       progress_interval: int = 10000,
+                       replacements_log: Optional[TextIO] = None) -> Dict:
+        """
+        Process text line by line from any readable stream into a writable one.
+
+        Replaced words are substituted in place, so whitespace and line
+        terminators are written as they were read.
+
+        Args:
+            reader: Text stream to read lines from (e.g. sys.stdin or io.StringIO)
+            writer: Text stream to write processed lines to
+            undo_log: If given, write one JSON undo record per replacement here,
+                tagged with its 0-based 'line', so restore_file can rebuild the
+                input exactly
+            progress: Called with a ProgressEvent every progress_interval lines
+                and once at the end
+            progress_interval: Number of lines between progress reports
+            replacements_log: If given, write one JSON object per replacement
+                here, with its 0-based 'line', word 'position', 'original'
+                and 'canonical'
+
+        Returns:
+            Dictionary of processing statistics. 'invalid_sequences' counts
+            runs of invalid UTF-8 found when invalid_utf8 is set; the streams
+            must then use the surrogateescape error handler. Lines that fail
+            to process are written unchanged and listed in 'errors' as dicts
+            with their 0-based 'line' and the 'error' message, unless
+            fail_fast is set
+        """
+        totals = _FileStatsAccumulator()
+        bytes_processed = 0
+
+        # Stream line by line so memory stays bounded on large corpora
+        for line_number, line in enumerate(reader):
+            content, ending = _split_line_ending(line)
+            invalid = 0
+            if self.invalid_utf8:
+                content, invalid = self._handle_invalid_bytes(content)
+            try:
+                # The undo log is a side channel; the output is the same either way
+                processed_line, stats, undo = self.process_text_reversible(content)
+            except Exception as e:
+                # One bad line shouldn't cost the rest of the file
+                if self.fail_fast:
+                    raise
+                writer.write(content + ending)
+                totals.add_error(f"{type(e).__name__}: {e}")
+            else:
+                if undo_log is not None:
+                    for record in undo:
+                        undo_log.write(json.dumps({'line': line_number, **record}) + '\n')
+                if replacements_log is not None:
+                    for r in stats['replacements']:
+                        replacements_log.write(json.dumps({
+                            'line': line_number,
+                            'position': r['position'],
+                            'original': r['original'],
+                            'canonical': r['canonical']
+                        }) + '\n')
+                writer.write(processed_line + ending)
+                totals.add(stats)
+            totals.invalid_sequences += invalid
+
+            if progress is not None:
+                bytes_processed += len(line.encode('utf-8', 'surrogateescape'))
+                if totals.total_lines % progress_interval == 0:
+                    progress(ProgressEvent(totals.total_lines, bytes_processed))
+
+        if progress is not None:
+            progress(ProgressEvent(totals.total_lines, bytes_processed))
+
+        return totals.result()
+
+    def _handle_invalid_bytes(self, content: str) -> Tuple[str, int]:
+        """Count runs of undecodable bytes in a line, replacing them under 'replace'."""
+        if self.invalid_utf8 == 'replace':
+            return _INVALID_BYTES_PATTERN.subn('\ufffd', content)
+        return content, len(_INVALID_BYTES_PATTERN.findall(content))
+
+    def process_csv(self, reader: TextIO, writer: TextIO, columns: Iterable[int],
+                    preserve_case: bool = True, delimiter: str = ',',
+                    has_header: bool = False) -> Dict:
+        """
+        Apply CVC transformation to selected columns of a CSV stream.
+
+        Records are written back exactly as read except for processed cells
+        that had a replacement: other columns, quoting, whitespace and line
+        terminators are untouched. A changed cell keeps its quotes if it was
+        quoted, and is quoted if the new value needs it. Fields are split as
+        the csv module's default dialect does.
+
+        Args:
+            reader: CSV text stream, opened with newline=''
+            writer: Stream to write the processed CSV to, opened with newline=''
+            columns: 0-based indices of the columns to process; rows too short
+                to have a column are left as they are
+            preserve_case: Whether to preserve original capitalization
+            delimiter: Field separator (a single character)
+            has_header: Leave the first record (the column names) unchanged
+
+        Returns:
+            Dictionary of processing statistics, with 'total_lines' counting
+            CSV records (a quoted field may span several lines)
+        """
+        columns = sorted(set(columns))
+        if any(column < 0 for column in columns):
+            raise ValueError("CSV column indices must be 0 or greater")
+        if len(delimiter) != 1 or delimiter in '"\r\n':
+            raise ValueError("CSV delimiter must be a single character other than a quote or newline")
+
+        totals = _FileStatsAccumulator()
+        for number, (record, fields) in enumerate(_csv_records(reader, delimiter)):
+            if fields is None or (has_header and number == 0):
+                writer.write(record)
+                totals.add(merge_statistics([]))
+                continue
+
+            cells = []
+            pieces = []
+            previous_end = 0
+            for index in columns:
+                if index >= len(fields):
+                    break
+                start, end, value, quoted = fields[index]
+                _, stats = self.process_text(value, preserve_case)
+                cells.append((value, stats))
+                if stats['replacements']:
+                    processed = _apply_replacements(value, stats['replacements'])
+                    pieces += [record[previous_end:start], _csv_field(processed, quoted, delimiter)]
+                    previous_end = end
+            writer.write(''.join(pieces) + record[previous_end:])
+            totals.add(merge_statistics(cells))
+
+        return totals.result()
+
+    def process_file_parallel(self, input_file: str, output_file: str,
+                              workers: Optional[int] = None,
+                              chunk_size: int = 10000) -> Dict:
+        """
+        Process an entire file with CVC transformation across worker processes.
+
+        Lines are processed in chunks and written back in their original
+        order, so the output and statistics match process_file exactly.
+
+        Args:
+            input_file: Path to input file
+            output_file: Path to output file
+            workers: Number of worker processes (defaults to CPU count)
+            chunk_size: Number of lines handed to a worker at a time
+
+        Returns:
+            Dictionary of processing statistics
+
+        Raises:
+            InputFileError: See process_file
+        """
+        if _same_file(input_file, output_file):
+            return _write_replacing(output_file, lambda temp_path: self.process_file_parallel(
+                input_file, temp_path, workers, chunk_size))
+
+        workers = workers or os.cpu_count() or 1
+        totals = _FileStatsAccumulator()
+
+        io_options = _text_io_options(self.invalid_utf8)
+        with _open_input(input_file, newline='', **io_options) as fin, \
+                open(output_file, 'w', newline='', **io_options) as fout, \
+                ProcessPoolExecutor(max_workers=workers,
+                                    initializer=_init_worker,
+                                    initargs=(self,)) as executor:
+            pending = deque()
+
+            def drain_one():
+                for processed_line, stats in pending.popleft().result():
+                    fout.write(processed_line)
+                    if 'error' in stats:
+                        totals.add_error(stats['error'])
+                    else:
+                        totals.add(stats)
+
+            # Keep a bounded number of chunks in flight to cap memory use
+            while True:
+                chunk = list(islice(fin, chunk_size))
+                if not chunk:
+                    break
+                pending.append(executor.submit(_process_chunk, chunk))
+                if len(pending) >= workers * 2:
+                    drain_one()
+
+            while pending:
+                drain_one()
+
+        return {
+            'input_file': input_file,
+            'output_file': output_file,
+            **totals.result()
+        }
+
+    def get_vocabulary_stats(self, text_file: str, token_encoder=None,
+                             stopwords: Optional[Iterable[str]] = None) -> Dict:
+        """
+        Analyze vocabulary statistics of a file before and after CVC.
+
+        Args:
+            text_file: Path to text file to analyze
+            token_encoder: See get_vocabulary_stats_from_text
+            stopwords: See get_vocabulary_stats_from_text
+
+        Returns:
+            Dictionary of vocabulary statistics
+
+        Raises:
+            InputFileError: If text_file cannot be read or, unless
+                invalid_utf8 is set, is not valid UTF-8
+        """
+        # Invalid bytes only need to be counted as non-words here
+        with _open_input(text_file, errors='replace' if self.invalid_utf8 else None) as f:
+            text = f.read()
+
+        return self.get_vocabulary_stats_from_text(text, token_encoder, stopwords)
+
+    def get_vocabulary_stats_from_text(self, text: str, token_encoder=None,
+                                       stopwords: Optional[Iterable[str]] = None) -> Dict:
+        """
+        Analyze vocabulary statistics of a string before and after CVC.
+
+        Byte counts are UTF-8 sizes of the text and of the text with only the
+        replaced words substituted (whitespace left as is).
+
+        Args:
+            text: Text to analyze
+            token_encoder: Optional tokenizer with an encode(text) method (e.g. a
+                Hugging Face or tiktoken tokenizer) to also report token counts
+            stopwords: Optional words (e.g. ENGLISH_STOPWORDS) left out of the
+                vocabulary sizes so the reduction reflects content words
+
+        Returns:
+            Dictionary of vocabulary statistics
+        """
+        # Original vocabulary, keeping where each word lies in text
+        original_words = []
+        for match in _VOCAB_WORD_PATTERN.finditer(text):
+            word = match.group().lower()
+            if _VOCAB_WORD_PATTERN.fullmatch(word):
+                original_words.append((word, match.start(), match.end()))
+            else:
+                # Lowercasing can introduce non-word characters (e.g. 'İ')
+                original_words.extend((w, match.start(), match.end())
+                                      for w in _VOCAB_WORD_PATTERN.findall(word))
+        original_vocab = {word for word, _, _ in original_words}
+
+        # Process text; only replaced spans change, so the processed vocabulary
+        # is the untouched original words plus the canonical words, plus words
+        # a replacement only partly covers (e.g. 'large2' -> 'big2'), which are
+        # rebuilt from their substituted text
+        _, stats = self.process_text(text)
+        replacements = sorted(stats['replacements'], key=lambda r: r['start'])
+        processed_vocab = set()
+        absorbed = set()
+        k = 0
+        for word, start, end in original_words:
+            while k < len(replacements) and replacements[k]['end'] <= start:
+                k += 1
+            if k == len(replacements) or end <= replacements[k]['start']:
+                processed_vocab.add(word)
+            elif start < replacements[k]['start'] or end > replacements[k]['end']:
+                touched = []
+                j = k
+                while j < len(replacements) and replacements[j]['start'] < end:
+                    touched.append(replacements[j])
+                    absorbed.add(j)
+                    j += 1
+                region_start = min(start, touched[0]['start'])
+                region_end = max(end, touched[-1]['end'])
+                substituted = _apply_replacements(text[region_start:region_end], [
+                    {**r, 'start': r['start'] - region_start, 'end': r['end'] - region_start}
+                    for r in touched
+                ])
+                processed_vocab.update(_VOCAB_WORD_PATTERN.findall(substituted.lower()))
+        for j, replacement in enumerate(replacements):
+            if j not in absorbed:
+                processed_vocab.update(_VOCAB_WORD_PATTERN.findall(replacement['canonical'].lower()))
+
+        if stopwords is not None:
+            excluded = {w.lower() for w in stopwords}
+            original_vocab -= excluded
+            processed_vocab -= excluded
+
+        # Calculate statistics
+        vocab_reduction = len(original_vocab) - len(processed_vocab)
+        reduction_rate = vocab_reduction / len(original_vocab) if original_vocab else 0
+
+        substituted_text = _apply_replacements(text, stats['replacements'])
+        original_bytes = _utf8_size(text)
+        processed_bytes = _utf8_size(substituted_text)
+
+        vocab_stats = {
+            'original_vocabulary_size': len(original_vocab),
+            'processed_vocabulary_size': len(processed_vocab),
+            'vocabulary_reduction': vocab_reduction,
+            'reduction_rate': reduction_rate,
+            'total_words': len(original_words),
+            'original_bytes': original_bytes,
+            'processed_bytes': processed_bytes,
+            'byte_reduction_rate': (original_bytes - processed_bytes) / original_bytes if original_bytes else 0
+        }
+
+        if token_encoder is not None:
+            original_tokens = len(token_encoder.encode(text))
+            processed_tokens = len(token_encoder.encode(substituted_text))
+            vocab_stats.update({
+                'original_tokens': original_tokens,
+                'processed_tokens': processed_tokens,
+                'token_reduction_rate': (original_tokens - processed_tokens) / original_tokens if original_tokens else 0
+            })
+
+        return vocab_stats
+
+    def suggest_candidates(self, text_file: str, min_count: int = 5) -> List[Tuple[str, int]]:
+        """
+        Find frequent words in a file that have no mapping yet.
+
+        Words are lowercased runs of word characters, as in
+        get_vocabulary_stats; words that are already a synonym or a canonical
+        form are left out.
+
+        Args:
+            text_file: Path to text file to scan
+            min_count: Minimum number of occurrences for a word to be listed
+
+        Returns:
+            List of (word, count) pairs, most frequent first (ties sorted
+            alphabetically)
+
+        Raises:
+            InputFileError: See get_vocabulary_stats
+        """
+        with _open_input(text_file, errors='replace' if self.invalid_utf8 else None) as f:
+            text = f.read()
+
+        counts = Counter(_VOCAB_WORD_PATTERN.findall(text.lower()))
+        candidates = [
+            (word, count) for word, count in counts.items()
+            if count >= min_count and word not in self.case_insensitive_lookup
+            and word not in self.canonical_lookup
+        ]
+        return sorted(candidates, key=lambda item: (-item[1], item[0]))
+
+    def get_ngram_stats(self, text: str, n: int = 2, top: int = 10) -> Dict:
+        """
+        Compare n-gram counts in a string before and after CVC.
+
+        Words are counted lowercased as in get_vocabulary_stats, and n-grams do
+        not span line breaks. Canonicalization usually collapses n-grams, e.g.
+        'large dog' and 'huge dog' both become 'big dog'. This is heavier than
+        get_vocabulary_stats, so it is a separate method.
+
+        Args:
+            text: Text to analyze
+            n: Number of words per n-gram
+            top: Number of n-grams to report in each of top_gains/top_losses
+
+        Returns:
+            Dictionary with the distinct n-gram counts before and after, their
+            reduction, and 'top_gains'/'top_losses': the n-grams whose count
+            rose or fell the most, as dicts with 'ngram', 'before', 'after'
+            and 'change'
+
+        Raises:
+            ValueError: If n is less than 1
+        """
+        if n < 1:
+            raise ValueError("n must be at least 1")
+
+        _, stats = self.process_text(text)
+        substituted_text = _apply_replacements(text, stats['replacements'])
+
+        def count_ngrams(source: str) -> Counter:
+            counts = Counter()
+            for line in source.splitlines():
+                words = _VOCAB_WORD_PATTERN.findall(line.lower())
+                counts.update(' '.join(words[i:i + n]) for i in range(len(words) - n + 1))
+            return counts
+
+        before = count_ngrams(text)
+        after = count_ngrams(substituted_text)
+
+        shifts = [
+            {'ngram': ngram, 'before': before[ngram], 'after': after[ngram],
+             'change': after[ngram] - before[ngram]}
+            for ngram in sorted(before.keys() | after.keys())
+            if before[ngram] != after[ngram]
+        ]
+        reduction = len(before) - len(after)
+
+        return {
+            'n': n,
+            'total_ngrams': sum(before.values()),
+            'original_distinct_ngrams': len(before),
+            'processed_distinct_ngrams': len(after),
+            'ngram_reduction': reduction,
+            'reduction_rate': reduction / len(before) if before else 0,
+            'top_gains': sorted((s for s in shifts if s['change'] > 0),
+                                key=lambda s: -s['change'])[:top],
+            'top_losses': sorted((s for s in shifts if s['change'] < 0),
+                                 key=lambda s: s['change'])[:top]
+        }
+
+
+def _ends_with_abbreviation(text: str) -> bool:
+    """Whether text ends with an abbreviation or initial whose period does not end a sentence."""
+    word = re.search(r'(\S+)$', text)
+    word = word.group(1).lower().lstrip('\'"([') if word else ''
+    return word in SENTENCE_ABBREVIATIONS or bool(re.fullmatch(r'[^\W\d_]', word))
+
+
+def split_sentences(text: str) -> List[str]:
+    """
+    Split text into sentences with a punctuation heuristic.
+
+    A sentence ends at '.', '!' or '?' (plus any closing quotes or brackets)
+    followed by whitespace and an uppercase letter, digit or opening quote.
+    Periods after known abbreviations (see SENTENCE_ABBREVIATIONS) and single
+    initials such as "J." do not end a sentence.
+
+    Args:
+        text: Text to split
+
+    Returns:
+        Sentences with surrounding whitespace removed
+    """
+    sentences = []
+    start = 0
+    for match in re.finditer(r'[.!?]+[\'")\]\u2019\u201d]*(?=\s+[\'"(\[\u2018\u201c]*[A-Z0-9])', text):
+        if match.group().startswith('.') and not match.group().startswith('...'):
+            if _ends_with_abbreviation(text[start:match.start()]):
+                continue
+        sentence = text[start:match.end()].strip()
+        if sentence:
+            sentences.append(sentence)
+        start = match.end()
+
+    rest = text[start:].strip()
+    if rest:
+        sentences.append(rest)
+    return sentences
+
+
+def _split_literals(text: str, pattern: re.Pattern) -> List[Tuple[str, bool]]:
+    """Split text into (segment, is_prose) pieces, with pattern matches not prose."""
+    segments = []
+    last_end = 0
+    for match in pattern.finditer(text):
+        segments.append((text[last_end:match.start()], True))
+        segments.append((match.group(), False))
+        last_end = match.end()
+    segments.append((text[last_end:], True))
+    return [(segment, is_prose) for segment, is_prose in segments if segment]
+
+
+def _markdown_segments(text: str) -> List[Tuple[str, bool]]:
+    """Split Markdown into (segment, is_prose) pieces that cover text in order."""
+    segments = []
+    prose = []
+    fence = None
+    after_break = True  # previous line was blank or indented code
+
+    def flush_prose():
+        segments.extend(_split_literals(''.join(prose), _MD_LITERAL_PATTERN))
+        prose.clear()
+
+    for line in text.splitlines(keepends=True):
+        if fence:
+            segments.append((line, False))
+            if re.match(rf'^ {{0,3}}{re.escape(fence[0])}{{{len(fence)},}}\s*$', line):
+                fence = None
+            continue
+
+        match = _MD_FENCE_PATTERN.match(line)
+        if match:
+            flush_prose()
+            fence = match.group(1)
+            segments.append((line, False))
+            continue
+
+        if after_break and line.strip() and re.match(r'^(?: {4}|\t)', line):
+            flush_prose()
+            segments.append((line, False))
+            continue
+
+        after_break = not line.strip()
+        prose.append(line)
+
+    flush_prose()
+    return [(segment, is_prose) for segment, is_prose in segments if segment]
+
+
+def _utf8_size(text: str) -> int:
+    """UTF-8 size of text in bytes, counting lone surrogates instead of failing on them."""
+    return len(text.encode('utf-8', 'surrogatepass'))
+
+
+def _text_io_options(invalid_utf8: Optional[str]) -> Dict:
+    """Keyword arguments for open() that let invalid UTF-8 through for invalid_utf8."""
+    if invalid_utf8 is None:
+        return {}
+    if invalid_utf8 not in INVALID_UTF8_MODES:
+        raise ValueError(f"invalid_utf8 must be one of {', '.join(INVALID_UTF8_MODES)} or None")
+    return {'encoding': 'utf-8', 'errors': 'surrogateescape'}
+
+
+@contextmanager
+def _decoding_input(name: str, stream: TextIO) -> Iterator[TextIO]:
+    """Raise InputFileError for invalid UTF-8 read from stream while the block runs."""
+    try:
+        yield stream
+    except UnicodeDecodeError as e:
+        raise InputFileError(f"{name} is not valid UTF-8 ({e.reason}); see the invalid_utf8 "
+                             f"option (--invalid-utf8) to process it anyway") from e
+
+
+@contextmanager
+def _open_input(path: str, **options) -> Iterator[TextIO]:
+    """Open a text file for reading, raising InputFileError if it cannot be read."""
+    try:
+        f = open(path, 'r', **options)
+    except OSError as e:
+        raise InputFileError(f"Failed to read input file {path}: {e}") from e
+
+    with f, _decoding_input(f"Input file {path}", f):
+        yield f
+
+
+def _csv_records(reader: TextIO, delimiter: str) -> Iterator[Tuple[str, Optional[List]]]:
+    """
+    Read CSV records from a stream with their raw text.
+
+    Yields (record, fields) pairs, where record is the text of the record
+    including its line terminator, and fields holds (start, end, value,
+    quoted) for each field, start:end being the field's raw text within
+    record. fields is None for a final record that ends inside quotes.
+
+    The csv module is not used because it only returns parsed values, while
+    records have to be kept verbatim so that unchanged fields, quoting and
+    line endings are written back exactly as they were read.
+    """
+    lines = []
+    quoted = False
+    for line in reader:
+        lines.append(line)
+        quoted = _csv_quote_open(line, delimiter, quoted)
+        if not quoted:
+            record = ''.join(lines)
+            yield record, _csv_fields(_split_line_ending(record)[0], delimiter)
+            lines = []
+    if lines:
+        yield ''.join(lines), None
+
+
+def _csv_quote_open(line: str, delimiter: str, quoted: bool) -> bool:
+    """
+    Whether a quoted field is still open at the end of a line of a CSV record.
+
+    quoted tells whether the line continues a quoted field from the previous
+    line; otherwise the line starts a new record.
+    """
+    position = 0
+    while True:
+        if quoted:
+            close = line.find('"', position)
+            if close < 0:
+                return True
+            position = close + 1
+            if line.startswith('"', position):
+                # A doubled quote stands for one quote character
+                position += 1
+                continue
+            quoted = False
+        elif line.startswith('"', position):
+            quoted = True
+            position += 1
+            continue
+        end = line.find(delimiter, position)
+        if end < 0:
+            return False
+        position = end + 1
+
+
+def _csv_fields(record: str, delimiter: str) -> Optional[List[Tuple[int, int, str, bool]]]:
+    """Split a CSV record without its terminator into fields (None if a quote is left open)."""
+    fields = []
+    start = 0
+    while True:
+        if record.startswith('"', start):
+            value = []
+            position = start + 1
+            while True:
+                close = record.find('"', position)
+                if close < 0:
+                    return None
+                value.append(record[position:close])
+                if not record.startswith('"', close + 1):
+                    break
+                # A doubled quote stands for one quote character
+                value.append('"')
+                position = close + 2
+            end = record.find(delimiter, close + 1)
+            end = len(record) if end < 0 else end
+            # Like the csv module, keep any text between the closing quote and the delimiter
+            value.append(record[close + 1:end])
+            fields.append((start, end, ''.join(value), True))
+        else:
+            end = record.find(delimiter, start)
+            end = len(record) if end < 0 else end
+            fields.append((start, end, record[start:end], False))
+        if end == len(record):
+            return fields
+        start = end + 1
+
+
+def _csv_field(value: str, quoted: bool, delimiter: str) -> str:
+    """Format a CSV field value, quoting it if it was quoted or has to be."""
+    if quoted or any(c in value for c in (delimiter, '"', '\r', '\n')):
+        return '"' + value.replace('"', '""') + '"'
+    return value
+
+
+def _join_words(span: List[Tuple[str, int, int]]) -> str:
+    """Join (word, start, end) words with a space where text separated them."""
+    pieces = [span[0][0]]
+    for previous, (word, start, _) in zip(span, span[1:]):
+        if start > previous[2]:
+            pieces.append(' ')
+        pieces.append(word)
+    return ''.join(pieces)
+
+
+def _same_file(path: str, other: str) -> bool:
+    """Whether two paths name the same existing file."""
+    try:
+        return os.path.samefile(path, other)
+    except OSError:
+        return False
+
+
+def _write_replacing(path: str, write: Callable[[str], Dict]) -> Dict:
+    """
+    Have write fill a temporary file next to path, then rename it over path.
+
+    The rename is atomic, so path is either left as it was (if write raises)
+    or fully replaced.
+    """
+    directory, name = os.path.split(os.path.abspath(path))
+    fd, temp_path = tempfile.mkstemp(dir=directory, prefix=f'.{name}.', suffix='.tmp')
+    os.close(fd)
+    try:
+        if os.path.exists(path):
+            shutil.copymode(path, temp_path)
+        result = write(temp_path)
+        os.replace(temp_path, path)
+    except BaseException:
+        os.remove(temp_path)
+        raise
+    return result
+
+
+def _split_line_ending(line: str) -> Tuple[str, str]:
+    """Split a line read with newline='' into its content and terminator."""
+    content = line.rstrip('\r\n')
+    return content, line[len(content):]
+
+
+def _kept_tokens(text: str, start: int, end: int) -> Iterator[KeptToken]:
+    """Yield a KeptToken for each whitespace-separated piece of text[start:end]."""
+    for m in re.finditer(r'\S+', text[start:end]):
+        yield KeptToken(m.group(), start + m.start(), start + m.end())
+
+
+def _apply_replacements(text: str, replacements: List[Dict]) -> str:
+    """Substitute each replacement into text at its start/end span."""
+    pieces = []
+    last_end = 0
+    for replacement in sorted(replacements, key=lambda r: r['start']):
+        pieces.append(text[last_end:replacement['start']])
+        pieces.append(replacement['canonical'])
+        last_end = replacement['end']
+    pieces.append(text[last_end:])
+    return ''.join(pieces)
+
+
+def apply_undo(processed_text: str, undo_log: List[Dict]) -> str:
+    """
+    Restore text compressed by process_text_reversible.
+
+    Args:
+        processed_text: Output of process_text_reversible
+        undo_log: Undo records returned alongside it
+
+    Returns:
+        The original text
+    """
+    restored = [{**record, 'canonical': record['original']} for record in undo_log]
+    return _apply_replacements(processed_text, restored)
+
+
+def restore_file(processed_file: str, undo_log_file: str, output_file: str):
+    """
+    Restore a file compressed by process_file with an undo log.
+
+    Args:
+        processed_file: Path to the processed file
+        undo_log_file: Path to the JSONL undo log written by process_file
+        output_file: Path to write the restored original text to
+
+    Raises:
+        InputFileError: If processed_file or undo_log_file cannot be read
+    """
+    undo_by_line: Dict[int, List[Dict]] = {}
+    with _open_input(undo_log_file) as f:
+        for record_line in f:
+            if record_line.strip():
+                record = json.loads(record_line)
+                undo_by_line.setdefault(record['line'], []).append(record)
+
+    with _open_input(processed_file, newline='') as fin, open(output_file, 'w', newline='') as fout:
+        for line_number, line in enumerate(fin):
+            content, ending = _split_line_ending(line)
+            fout.write(apply_undo(content, undo_by_line.get(line_number, [])) + ending)
+
+
+# Largest request body, in bytes, make_server accepts by default
+MAX_REQUEST_BYTES = 10 * 1024 * 1024
+
+
+def make_server(processor: CVCProcessor, host: str = '127.0.0.1',
+                port: int = 8080, max_request_bytes: int = MAX_REQUEST_BYTES) -> ThreadingHTTPServer:
+    """
+    Create an HTTP server exposing a processor over JSON.
+
+    Endpoints:
+        POST /process: body {"text": ..., "preserve_case": true}, responds
+            with {"text": ..., "stats": ...} as returned by process_text
+        GET /health: responds with {"status": "ok"}
+
+    Requests are handled on separate threads sharing the one processor,
+    which is safe as long as its mappings are not modified while serving.
+    A POST needs a valid Content-Length: without one the response is 411,
+    a malformed or negative one gets 400, and a body over
+    max_request_bytes is refused with 413 without being read.
+
+    Args:
+        processor: Configured processor used for every request
+        host: Interface to listen on
+        port: Port to listen on (0 picks a free one)
+        max_request_bytes: Largest request body accepted
+
+    Returns:
+        Server ready for serve_forever()
+    """
+    class Handler(BaseHTTPRequestHandler):
+        def send_json(self, status: int, body: Dict):
+            data = json.dumps(body).encode('utf-8')
+            self.send_response(status)
+            self.send_header('Content-Type', 'application/json')
+            self.send_header('Content-Length', str(len(data)))
+            self.end_headers()
+            self.wfile.write(data)
+
+        def do_GET(self):
+            if self.path == '/health':
+                self.send_json(200, {'status': 'ok'})
+            else:
+                self.send_json(404, {'error': f'no such endpoint: {self.path}'})
+
+        def do_POST(self):
+            if self.path != '/process':
+                self.send_json(404, {'error': f'no such endpoint: {self.path}'})
+                return
+
+            header = self.headers.get('Content-Length')
+            if header is None:
+                self.close_connection = True
+                self.send_json(411, {'error': 'Content-Length is required'})
+                return
+            value = header.strip()
+            # ASCII digits only; int() would also take '+1', '1_0' and other scripts' digits
+            length = int(value) if value.isascii() and value.isdigit() and len(value) < 20 else -1
+            if length < 0:
+                self.close_connection = True
+                self.send_json(400, {'error': f'invalid Content-Length: {header!r}'})
+                return
+            if length > max_request_bytes:
+                # The body is left unread, so the connection cannot be reused
+                self.close_connection = True
+                self.send_json(413, {'error': f'request body over {max_request_bytes} bytes'})
+                return
+
+            try:
+                request = json.loads(self.rfile.read(length))
+                text = request['text']
+                preserve_case = request.get('preserve_case', True)
+                if not isinstance(text, str) or not isinstance(preserve_case, bool):
+                    raise TypeError
+            except (ValueError, KeyError, TypeError, AttributeError, RecursionError):
+                self.send_json(400, {'error': 'expected a JSON object with a string "text" '
+                                              'and optional boolean "preserve_case"'})
+                return
+
+            processed_text, stats = processor.process_text(text, preserve_case)
+            self.send_json(200, {'text': processed_text, 'stats': stats})
+
+    return ThreadingHTTPServer((host, port), Handler)
+
+
+def replacement_frequency(stats: Dict) -> Dict[str, int]:
+    """
+    Count how often each synonym was replaced.
+
+    Args:
+        stats: Statistics from process_text, or from process_file/process_stream
+
+    Returns:
+        Dictionary mapping each original synonym (lowercased) to its count
+    """
+    if 'replacement_frequency' in stats:
+        return dict(stats['replacement_frequency'])
+    return dict(Counter(r['original'].lower() for r in stats['replacements']))
+
+
+def top_replacements(stats: Dict, n: int) -> List[Tuple[str, int]]:
+    """
+    Get the most frequently replaced synonyms.
+
+    Args:
+        stats: Statistics from process_text, or from process_file/process_stream
+        n: Maximum number of entries to return
+
+    Returns:
+        List of (synonym, count) pairs, most frequent first (ties alphabetical)
+    """
+    counts = replacement_frequency(stats)
+    return sorted(counts.items(), key=lambda item: (-item[1], item[0]))[:n]
+
+
+def merge_statistics(parts: Iterable[Tuple[str, Dict]], separator: str = '') -> Dict:
+    """
+    Combine process_text statistics for consecutive chunks of one text.
+
+    Replacement positions and start/end offsets are shifted so they refer to
+    the whole text, as if it had been processed in a single call.
+
+    Args:
+        parts: (original_chunk, statistics) pairs in text order; the chunk is
+            the text passed to process_text, used to shift start/end offsets
+        separator: String the chunks were joined with in the whole text
+
+    Returns:
+        Statistics dictionary in the shape process_text returns
+    """
+    total_words = 0
+    mappable_words = 0
+    offset = 0
+    replacements = []
+    by_domain = Counter()
+
+    for i, (chunk, stats) in enumerate(parts):
+        if i:
+            offset += len(separator)
+        for r in stats['replacements']:
+            replacements.append({
+                **r,
+                'position': r['position'] + total_words,
+                'start': r['start'] + offset,
+                'end': r['end'] + offset
+            })
+        total_words += stats['total_words']
+        mappable_words += stats.get('mappable_words', stats['replacements_made'])
+        by_domain.update(stats['by_domain'])
+        offset += len(chunk)
+
+    return {
+        'total_words': total_words,
+        'replacements_made': len(replacements),
+        'replacement_rate': len(replacements) / total_words if total_words else 0,
+        'mappable_words': mappable_words,
+        'content_replacement_rate': len(replacements) / mappable_words if mappable_words else 0,
+        'replacements': replacements,
+        'by_domain': dict(by_domain)
+    }
+
+
+class _FileStatsAccumulator:
+    """Sums per-line process_text statistics into file-level statistics."""
+
+    def __init__(self):
+        self.total_lines = 0
+        self.total_words = 0
+        self.total_replacements = 0
+        self.mappable_words = 0
+        self.invalid_sequences = 0
+        self.errors: List[Dict] = []
+        self.frequency = Counter()
+        self.by_domain = Counter()
+
+    def add(self, stats: Dict):
+        """Add one line's statistics."""
+        self.total_lines += 1
+        self.total_words += stats['total_words']
+        self.total_replacements += stats['replacements_made']
+        self.mappable_words += stats.get('mappable_words', stats['replacements_made'])
+        self.invalid_sequences += stats.get('invalid_sequences', 0)
+        self.frequency.update(r['original'].lower() for r in stats['replacements'])
+        self.by_domain.update(stats['by_domain'])
+
+    def add_error(self, error: str):
+        """Count a line that failed to process, recording its error."""
+        self.errors.append({'line': self.total_lines, 'error': error})
+        self.total_lines += 1
+
+    def add_file(self, stats: Dict):
+        """Add a whole file's statistics, as returned by result()."""
+        self.total_lines += stats['total_lines']
+        self.total_words += stats['total_words']
+        self.total_replacements += stats['total_replacements']
+        self.mappable_words += stats['mappable_words']
+        self.invalid_sequences += stats['invalid_sequences']
+        self.errors.extend(stats['errors'])
+        self.frequency.update(stats['replacement_frequency'])
+        self.by_domain.update(stats['by_domain'])
+
+    def result(self) -> Dict:
+        """Build the file-level statistics dictionary."""
+        return {
+            'total_lines': self.total_lines,
+            'total_words': self.total_words,
+            'total_replacements': self.total_replacements,
+            'replacement_rate': self.total_replacements / self.total_words if self.total_words else 0,
+            'mappable_words': self.mappable_words,
+            'content_replacement_rate':
+                self.total_replacements / self.mappable_words if self.mappable_words else 0,
+            'invalid_sequences': self.invalid_sequences,
+            'errors': self.errors,
+            'replacement_frequency': dict(self.frequency),
+            'by_domain': dict(self.by_domain)
+        }
+
+
+class CVCProcessorBuilder:
+    """
+    Chainable configuration for a CVCProcessor.
+
+    Example:
+        processor = (CVCProcessorBuilder()
+                     .mapping_file('mappings/synonym_to_canonical.json')
+                     .domains(['medical'])
+                     .max_frequency_rank(1)
+                     .case_strategy(CaseStrategy.EXACT)
+                     .build())
+    """
+
+    def __init__(self):
+        """Start from the default configuration and the bundled mappings."""
+        self._mapping_file: Optional[Union[str, os.PathLike]] = None
+        self._mapping_data: Optional[Dict] = None
+        self._domains: Optional[Set[str]] = None
+        self._strict = False
+        self._options: Dict = {}
+
+    def mapping_file(self, path: Union[str, os.PathLike]) -> 'CVCProcessorBuilder':
+        """Load mappings from a file."""
+        self._mapping_file, self._mapping_data = path, None
+        return self
+
+    def mapping_data(self, data: Dict) -> 'CVCProcessorBuilder':
+        """Use already-parsed mapping data."""
+        self._mapping_data, self._mapping_file = data, None
+        return self
+
+    def strict(self, strict: bool = True) -> 'CVCProcessorBuilder':
+        """Fail to build on conflicting synonyms instead of warning."""
+        self._strict = strict
+        return self
+
+    def domains(self, domains: Optional[Iterable[str]]) -> 'CVCProcessorBuilder':
+        """Restrict replacements to mappings from these domains."""
+        self._domains = set(domains) if domains is not None else None
+        return self
+
+    def min_frequency_rank(self, rank: Optional[int]) -> 'CVCProcessorBuilder':
+        """Only apply mappings with frequency_rank >= rank."""
+        self._options['min_frequency_rank'] = rank
+        return self
+
+    def max_frequency_rank(self, rank: Optional[int]) -> 'CVCProcessorBuilder':
+        """Only apply mappings with frequency_rank <= rank."""
+        self._options['max_frequency_rank'] = rank
+        return self
+
+    def case_strategy(self, strategy: CaseStrategy) -> 'CVCProcessorBuilder':
+        """Set how replacement capitalization is derived."""
+        self._options['case_strategy'] = strategy
+        return self
+
+    def tokenizer(self, tokenizer: Tokenizer) -> 'CVCProcessorBuilder':
+        """Set how text is split into words."""
+        self._options['tokenizer'] = tokenizer
+        return self
+
+    def max_phrase_len(self, length: int) -> 'CVCProcessorBuilder':
+        """Set the longest multi-word synonym (in words) to match."""
+        if length < 1:
+            raise ValueError("max_phrase_len must be at least 1")
+        self._options['max_phrase_len'] = length
+        return self
+
+    def split_compounds(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Process hyphenated compounds and contractions."""
+        self._options['split_compounds'] = enabled
+        return self
+
+    def resolve_chains(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Follow chained mappings to their terminal canonical."""
+        self._options['resolve_chains'] = enabled
+        return self
+
+    def unicode_normalization(self, form: Optional[str]) -> 'CVCProcessorBuilder':
+        """Set the Unicode normalization form used for lookups (None disables it)."""
+        self._options['unicode_normalization'] = form
+        return self
+
+    def invalid_utf8(self, mode: Optional[str]) -> 'CVCProcessorBuilder':
+        """Pass through ('pass') or replace ('replace') invalid UTF-8 input bytes."""
+        if mode is not None and mode not in INVALID_UTF8_MODES:
+            raise ValueError(f"invalid_utf8 must be one of {', '.join(INVALID_UTF8_MODES)} or None")
+        self._options['invalid_utf8'] = mode
+        return self
+
+    def min_word_len(self, length: int) -> 'CVCProcessorBuilder':
+        """Leave words shorter than length characters unchanged."""
+        self._options['min_word_len'] = length
+        return self
+
+    def skip_all_caps(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Leave all-caps words (likely acronyms) unchanged."""
+        self._options['skip_all_caps'] = enabled
+        return self
+
+    def skip_midsentence_capitalized(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Leave Title-Case words that don't start a sentence (likely proper nouns) unchanged."""
+        self._options['skip_midsentence_capitalized'] = enabled
+        return self
+
+    def fail_fast(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Abort on the first line that fails instead of leaving it unchanged."""
+        self._options['fail_fast'] = enabled
+        return self
+
+    def token_separator(self, separator: str) -> 'CVCProcessorBuilder':
+        """Set the string placed between rejoined whitespace tokens."""
+        self._options['token_separator'] = separator
+        return self
+
+    def intern_canonicals(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Share one string among repeated replacements of the same word."""
+        self._options['intern_canonicals'] = enabled
+        return self
+
+    def split_trailing_digits(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Look up 'large2' as 'large', keeping the digits after the replacement."""
+        self._options['split_trailing_digits'] = enabled
+        return self
+
+    def count_only_word_tokens(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Leave punctuation-only tokens out of total_words."""
+        self._options['count_only_word_tokens'] = enabled
+        return self
+
+    def skip_urls(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Leave tokens that look like URLs, email addresses or paths untouched."""
+        self._options['skip_urls'] = enabled
+        return self
+
+    def fuzzy_distance(self, distance: int) -> 'CVCProcessorBuilder':
+        """Match misspelled words within this edit distance (0 disables it)."""
+        if distance < 0:
+            raise ValueError("fuzzy_distance must not be negative")
+        self._options['fuzzy_distance'] = distance
+        return self
+
+    def accent_insensitive(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Also match words that differ from a key only in diacritics."""
+        self._options['accent_insensitive'] = enabled
+        return self
+
+    def case_sensitive(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Match mapping keys only with their exact capitalization."""
+        self._options['case_sensitive'] = enabled
+        return self
+
+    def never_replace(self, words: Iterable[str]) -> 'CVCProcessorBuilder':
+        """Keep these words verbatim even if they are mapped."""
+        self._options['never_replace'] = {w.lower() for w in words}
+        return self
+
+    def use_automaton(self, enabled: bool = True) -> 'CVCProcessorBuilder':
+        """Pre-scan text with a compiled pattern of all mapping keys."""
+        self._options['use_automaton'] = enabled
+        return self
+
+    def build(self) -> CVCProcessor:
+        """
+        Create the configured processor.
+
+        Returns:
+            Initialized CVCProcessor
+
+        Raises:
+            CVCError: If the mappings cannot be loaded
+        """
+        if self._mapping_data is not None:
+            processor = CVCProcessor.from_mappings(self._mapping_data, self._strict)
+        elif self._mapping_file is not None:
+            processor = CVCProcessor(self._mapping_file, self._strict)
+        else:
+            processor = CVCProcessor(str(BUNDLED_MAPPING_FILE), self._strict)
+
+        for name, value in self._options.items():
+            setattr(processor, name, value)
+        processor.set_active_domains(self._domains)
+
+        return processor
+
+
+# Processor shared by each worker process of process_file_parallel
+_worker_processor: Optional[CVCProcessor] = None
+
+
+def _init_worker(processor: CVCProcessor):
+    """Install the processor in a worker process."""
+    global _worker_processor
+    _worker_processor = processor
 
-        Args:
-            mapping_file: Path to JSON file containing synonym-to-canonical mappings
-        """
-        with open(mapping_file, 'r') as f:
-            data = json.load(f)
 
-        self.reverse_lookup = data['reverse_lookup']
-        self.mappings = data['mappings']
-        self.metadata = data.get('metadata', {})
+def _process_chunk(lines: List[str]) -> List[Tuple[str, Dict]]:
+    """Process a chunk of lines in a worker process, keeping line terminators."""
+    results = []
+    for line in lines:
+        content, ending = _split_line_ending(line)
+        invalid = 0
+        if _worker_processor.invalid_utf8:
+            content, invalid = _worker_processor._handle_invalid_bytes(content)
+        try:
+            processed_line, stats, _ = _worker_processor.process_text_reversible(content)
+        except Exception as e:
+            if _worker_processor.fail_fast:
+                raise
+            results.append((content + ending, {'error': f"{type(e).__name__}: {e}"}))
+            continue
+        results.append((processed_line + ending, {**stats, 'invalid_sequences': invalid}))
+    return results
 
-        # Build case-insensitive lookup for better matching
-        self.case_insensitive_lookup = {
-            k.lower(): v for k, v in self.reverse_lookup.items()
-        }
 
-    def process_text(self, text: str, preserve_case: bool = True) -> Tuple[str, Dict]:
-        """
-        Apply CVC transformation to input text.
+def _open_stream(path: str, mode: str, invalid_utf8: Optional[str] = None):
+    """Open a file, treating '-' as stdin (read) or stdout (write)."""
+    io_options = _text_io_options(invalid_utf8)
+    if path == '-':
+        stream = sys.stdin if 'r' in mode else sys.stdout
+        if io_options:
+            stream.reconfigure(errors=io_options['errors'])
+        return _decoding_input('stdin', stream) if 'r' in mode else nullcontext(stream)
+    # newline='' keeps \r\n line endings intact
+    if 'r' in mode:
+        return _open_input(path, newline='', **io_options)
+    return open(path, mode, newline='', **io_options)
 
-        Args:
-            text: Input text to process
-            preserve_case: Whether to preserve original capitalization
 
-        Returns:
-            Tuple of (processed_text, statistics)
-        """
-        words = text.split()
-        processed_words = []
-        replacements = []
+def _write_stats_json(report: Dict, stats_file: Optional[str], stdout_busy: bool = False):
+    """Write a statistics report as JSON to a file, or to the terminal."""
+    text = json.dumps(report, indent=2)
+    if stats_file:
+        with open(stats_file, 'w') as f:
+            f.write(text + '\n')
+    else:
+        # Keep stdout clean when it is already carrying processed text
+        print(text, file=sys.stderr if stdout_busy else sys.stdout)
 
-        for i, word in enumerate(words):
-            # Extract word without punctuation
-            match = re.match(r'^([^\w]*)(\w+)([^\w]*)$', word)
-            if not match:
-                processed_words.append(word)
-                continue
 
-            prefix, core_word, suffix = match.groups()
+def _display_name(path: str) -> str:
+    """Human-readable name for a CLI path argument."""
+    return 'stdin' if path == '-' else path
 
-            # Check for canonical mapping
-            canonical = self._get_canonical(core_word)
 
-            if canonical:
-                # Preserve original capitalization pattern
-                if preserve_case:
-                    canonical = self._preserve_case(core_word, canonical)
+def _column_list(value: str) -> List[int]:
+    """Parse a comma-separated list of 0-based column indices."""
+    try:
+        columns = [int(part) for part in value.split(',')]
+    except ValueError:
+        raise argparse.ArgumentTypeError(f"expected comma-separated column numbers, got '{value}'")
+    if any(column < 0 for column in columns):
+        raise argparse.ArgumentTypeError("column numbers must be 0 or greater")
+    return columns
 
-                processed_words.append(f"{prefix}{canonical}{suffix}")
-                replacements.append({
-                    'position': i,
-                    'original': core_word,
-                    'canonical': canonical
-                })
-            else:
-                processed_words.append(word)
 
-        processed_text = ' '.join(processed_words)
+def _add_filter_arguments(parser):
+    """Add the options that restrict which mappings are applied."""
+    parser.add_argument(
+        '--min-frequency-rank',
+        type=int,
+        help='Only apply mappings with frequency_rank >= this value'
+    )
+    parser.add_argument(
+        '--max-frequency-rank',
+        type=int,
+        help='Only apply mappings with frequency_rank <= this value'
+    )
+    parser.add_argument(
+        '--keep',
+        type=lambda value: [w.strip() for w in value.split(',') if w.strip()],
+        default=[],
+        help='Comma-separated words that are never replaced (e.g. --keep shall,party)'
+    )
+    parser.add_argument(
+        '--case-sensitive',
+        action='store_true',
+        help='Match mapping keys only with their exact capitalization'
+    )
+    parser.add_argument(
+        '--accent-insensitive',
+        action='store_true',
+        help='Also match words that differ from a mapping key only in diacritics'
+    )
+    parser.add_argument(
+        '--min-word-len',
+        type=int,
+        default=0,
+        metavar='N',
+        help='Leave words shorter than N characters unchanged (default: 0)'
+    )
+    parser.add_argument(
+        '--skip-all-caps',
+        action='store_true',
+        help='Leave all-caps words such as acronyms unchanged'
+    )
+    parser.add_argument(
+        '--token-separator',
+        default=' ',
+        metavar='SEP',
+        help='String placed between rejoined tokens by expand and serve; process keeps '
+             'the input\'s whitespace (default: a single space)'
+    )
+    parser.add_argument(
+        '--count-only-word-tokens',
+        action='store_true',
+        help="Leave punctuation-only tokens such as '--' out of the word count"
+    )
+    parser.add_argument(
+        '--split-trailing-digits',
+        action='store_true',
+        help="Look up words ending in digits without them, so 'large2' becomes 'big2'"
+    )
+    parser.add_argument(
+        '--skip-midsentence-capitalized',
+        action='store_true',
+        help='Leave capitalized words that do not start a sentence (likely names) unchanged'
+    )
+    parser.add_argument(
+        '--skip-urls',
+        action='store_true',
+        help='Leave tokens that look like URLs, email addresses or file paths untouched'
+    )
+    parser.add_argument(
+        '--fuzzy',
+        type=int,
+        default=0,
+        metavar='K',
+        help='Match misspelled words within edit distance K of a mapping key (default: off)'
+    )
+    parser.add_argument(
+        '--normalization',
+        choices=['NFC', 'NFKC', 'none'],
+        default='NFC',
+        help='Unicode normalization applied before lookup (NFKC also folds full-width forms)'
+    )
+    parser.add_argument(
+        '--invalid-utf8',
+        choices=['error', 'pass', 'replace'],
+        default='error',
+        help='How to handle input that is not valid UTF-8: fail, copy the bytes '
+             'through unchanged, or replace them with U+FFFD (default: error)'
+    )
+
 
-        statistics = {
-            'total_words': len(words),
-            'replacements_made': len(replacements),
-            'replacement_rate': len(replacements) / len(words) if words else 0,
-            'replacements': replacements
-        }
+def _add_stats_output_arguments(parser):
+    """Add the options controlling how statistics are reported."""
+    parser.add_argument(
+        '--stats-format',
+        choices=['text', 'json'],
+        default='text',
+        help='Format for printed statistics'
+    )
+    parser.add_argument(
+        '--stats-file',
+        help='Write statistics as JSON to this file instead of the terminal'
+    )
+    parser.add_argument(
+        '--exclude-stopwords',
+        action='store_true',
+        help='Leave common English function words out of vocabulary sizes'
+    )
 
-        return processed_text, statistics
 
-    def _get_canonical(self, word: str) -> Optional[str]:
-        """Get canonical form for a word."""
-        # Try exact match first
-        if word in self.reverse_lookup:
-            return self.reverse_lookup[word]
+def _configure_processor(processor: CVCProcessor, args):
+    """Apply CLI filter options to a processor."""
+    processor.min_frequency_rank = args.min_frequency_rank
+    processor.max_frequency_rank = args.max_frequency_rank
+    processor.set_never_replace(args.keep)
+    processor.case_sensitive = args.case_sensitive
+    processor.accent_insensitive = args.accent_insensitive
+    processor.fuzzy_distance = args.fuzzy
+    processor.skip_urls = args.skip_urls
+    processor.min_word_len = args.min_word_len
+    processor.skip_all_caps = args.skip_all_caps
+    processor.skip_midsentence_capitalized = args.skip_midsentence_capitalized
+    processor.count_only_word_tokens = args.count_only_word_tokens
+    processor.split_trailing_digits = args.split_trailing_digits
+    processor.token_separator = args.token_separator
+    processor.unicode_normalization = None if args.normalization == 'none' else args.normalization
+    processor.invalid_utf8 = None if args.invalid_utf8 == 'error' else args.invalid_utf8
 
-        # Try case-insensitive match
-        if word.lower() in self.case_insensitive_lookup:
-            return self.case_insensitive_lookup[word.lower()]
 
-        return None
+def _vocabulary_stats(processor: CVCProcessor, args) -> Dict:
+    """Compute vocabulary statistics for the CLI input file."""
+    stopwords = ENGLISH_STOPWORDS if args.exclude_stopwords else None
+    return processor.get_vocabulary_stats(args.input, stopwords=stopwords)
 
-    def _preserve_case(self, original: str, canonical: str) -> str:
-        """Preserve the capitalization pattern of original word."""
-        if original.isupper():
-            return canonical.upper()
-        elif original[0].isupper():
-            return canonical.capitalize()
+
+def _print_vocabulary_stats(vocab_stats: Dict):
+    """Print vocabulary statistics in human-readable form."""
+    print("\nVocabulary Statistics:", file=sys.stderr)
+    print(f"Original vocabulary size: {vocab_stats['original_vocabulary_size']}", file=sys.stderr)
+    print(f"Processed vocabulary size: {vocab_stats['processed_vocabulary_size']}", file=sys.stderr)
+    print(f"Vocabulary reduction: {vocab_stats['vocabulary_reduction']}", file=sys.stderr)
+    print(f"Reduction rate: {vocab_stats['reduction_rate']:.2%}", file=sys.stderr)
+    print(f"Bytes: {vocab_stats['original_bytes']} -> {vocab_stats['processed_bytes']} "
+          f"({vocab_stats['byte_reduction_rate']:.2%} smaller)", file=sys.stderr)
+
+
+def _progress_printer(input_path: str) -> Callable[[ProgressEvent], None]:
+    """Build a progress callback that redraws a status line on stderr."""
+    total_bytes = os.path.getsize(input_path) if input_path != '-' else None
+
+    def report(event: ProgressEvent):
+        status = f"\rProcessed {event.lines_processed:,} lines ({event.bytes_processed / 1e6:.1f} MB"
+        if total_bytes:
+            status += f" of {total_bytes / 1e6:.1f} MB, {event.bytes_processed / total_bytes:.0%}"
+        print(status + ")", end='', file=sys.stderr, flush=True)
+
+    return report
+
+
+def _process_document(processor: CVCProcessor, reader: TextIO, writer: TextIO, fmt: str) -> Dict:
+    """Process a whole structured document, returning file-level statistics."""
+    process = {'markdown': processor.process_markdown, 'html': processor.process_html}[fmt]
+    text = reader.read()
+    processed_text, stats = process(text)
+    writer.write(processed_text)
+
+    totals = _FileStatsAccumulator()
+    totals.add(stats)
+    return {**totals.result(), 'total_lines': len(text.splitlines())}
+
+
+def _process_input(processor: CVCProcessor, args) -> Dict:
+    """Run the 'process' subcommand's input through the processor once."""
+    if args.in_place:
+        return _process_in_place(processor, args)
+    if '-' not in (args.input, args.output) and _same_file(args.input, args.output):
+        # Writing straight to the input would truncate it before it is read
+        return _process_in_place(processor, args)
+
+    with _open_stream(args.input, 'r', processor.invalid_utf8) as fin, \
+            _open_stream(args.output, 'a' if args.append else 'w', processor.invalid_utf8) as fout, \
+            (open(args.undo_log, 'w') if args.undo_log else nullcontext()) as undo_log, \
+            (open(args.replacements_log, 'w') if args.replacements_log else nullcontext()) as log:
+        if args.format == 'csv':
+            return processor.process_csv(fin, fout, args.columns, has_header=args.header)
+        if args.format != 'text':
+            return _process_document(processor, fin, fout, args.format)
+
+        progress = _progress_printer(args.input) if args.progress else None
+        stats = processor.process_stream(fin, fout, undo_log, progress, replacements_log=log)
+        if progress:
+            print(file=sys.stderr)
+        return stats
+
+
+def _process_in_place(processor: CVCProcessor, args) -> Dict:
+    """Process the input file into a temporary file, then rename it over the input."""
+    return _write_replacing(args.input, lambda temp_path: _process_input(
+        processor, argparse.Namespace(**{**vars(args), 'output': temp_path, 'in_place': False})
+    ))
+
+
+# Seconds between checks of a watched file, and how long it must stay
+# unchanged after a write before it is reprocessed
+WATCH_POLL_INTERVAL = 0.5
+WATCH_DEBOUNCE = 0.3
+
+
+def _watch(processor: CVCProcessor, args) -> int:
+    """Reprocess the input file whenever it changes, until interrupted."""
+    def signature():
+        try:
+            stat = os.stat(args.input)
+        except FileNotFoundError:
+            return None
+        return stat.st_mtime_ns, stat.st_size
+
+    print(f"Watching {args.input} (Ctrl-C to stop)...", file=sys.stderr)
+    last = None
+    try:
+        while True:
+            current = signature()
+            if current is not None and current != last:
+                # Wait for a burst of writes to settle before reprocessing
+                time.sleep(WATCH_DEBOUNCE)
+                if signature() != current:
+                    continue
+                stats = _process_input(processor, args)
+                print(f"[{time.strftime('%H:%M:%S')}] {args.output}: {stats['total_replacements']} "
+                      f"replacements in {stats['total_words']} words "
+                      f"({stats['replacement_rate']:.2%})", file=sys.stderr)
+                last = current
+            time.sleep(WATCH_POLL_INTERVAL)
+    except KeyboardInterrupt:
+        return 0
+
+
+def _run_process(processor: CVCProcessor, args) -> int:
+    """Handle the 'process' subcommand."""
+    _configure_processor(processor, args)
+    processor.fail_fast = args.fail_fast
+
+    if args.dry_run:
+        # Show what would change without writing the output file
+        with _open_stream(args.input, 'r') as fin:
+            for line in fin:
+                print(processor.process_text_annotated(line.rstrip('\r\n')))
+        return 0
+
+    if args.in_place:
+        if args.input == '-':
+            raise CVCError("--in-place needs an --input file, not stdin")
+        if args.output != '-':
+            raise CVCError("--in-place writes back to --input; drop --output")
+        if args.watch:
+            raise CVCError("--in-place cannot be combined with --watch")
+        if args.append:
+            raise CVCError("--in-place cannot be combined with --append")
+        if not os.path.isfile(args.input):
+            raise CVCError(f"Input file not found: {args.input}")
+
+    # --output naming the input file works like --in-place
+    overwrites_input = args.in_place or \
+        ('-' not in (args.input, args.output) and _same_file(args.input, args.output))
+    if overwrites_input and not args.in_place:
+        if args.append:
+            raise CVCError("--output is the input file; it cannot be combined with --append")
+        if args.watch:
+            raise CVCError("--output is the input file; it cannot be combined with --watch")
+
+    if args.format == 'csv' and not args.columns:
+        raise CVCError("--format csv needs --columns")
+    if args.columns and args.format != 'csv':
+        raise CVCError("--columns is only supported with --format csv")
+    if args.header and args.format != 'csv':
+        raise CVCError("--header is only supported with --format csv")
+
+    if args.undo_log and args.format != 'text':
+        raise CVCError("--undo-log is only supported with --format text")
+    if args.undo_log and args.append:
+        raise CVCError("--undo-log cannot be combined with --append: its line numbers "
+                       "would not match the appended output")
+    if args.replacements_log and args.format != 'text':
+        raise CVCError("--replacements-log is only supported with --format text")
+
+    if args.watch:
+        if '-' in (args.input, args.output):
+            raise CVCError("--watch needs --input and --output files, not stdin/stdout")
+        if args.append:
+            raise CVCError("--watch rewrites the whole output; it cannot be combined with --append")
+        return _watch(processor, args)
+
+    # Vocabulary statistics read the input file, which may be overwritten
+    vocab_stats = _vocabulary_stats(processor, args) \
+        if args.stats and overwrites_input else None
+
+    # Process input; messages go to stderr so stdout can carry the output
+    print(f"Processing {_display_name(args.input)}...", file=sys.stderr)
+    stats = _process_input(processor, args)
+    for error in stats['errors']:
+        print(f"Warning: line {error['line'] + 1} left unchanged: {error['error']}", file=sys.stderr)
+
+    if args.stats_format == 'json' or args.stats_file:
+        report = {'processing': stats}
+        if args.stats and args.input != '-':
+            report['vocabulary'] = vocab_stats or _vocabulary_stats(processor, args)
+        _write_stats_json(report, args.stats_file, stdout_busy=args.output == '-' and not args.in_place)
+        return 0
+
+    print(f"\nProcessing complete!", file=sys.stderr)
+    print(f"Total lines: {stats['total_lines']}", file=sys.stderr)
+    print(f"Total words: {stats['total_words']}", file=sys.stderr)
+    print(f"Replacements made: {stats['total_replacements']}", file=sys.stderr)
+    print(f"Replacement rate: {stats['replacement_rate']:.2%}", file=sys.stderr)
+    print(f"Content replacement rate: {stats['content_replacement_rate']:.2%} "
+          f"of {stats['mappable_words']} mappable words", file=sys.stderr)
+    if stats['invalid_sequences']:
+        print(f"Invalid UTF-8 sequences: {stats['invalid_sequences']}", file=sys.stderr)
+
+    if args.stats:
+        if stats['by_domain']:
+            print("\nReplacements by domain:", file=sys.stderr)
+            for domain, count in sorted(stats['by_domain'].items(), key=lambda item: -item[1]):
+                print(f"  {domain}: {count}", file=sys.stderr)
+
+        top = top_replacements(stats, 10)
+        if top:
+            print("\nMost replaced words:", file=sys.stderr)
+            for word, count in top:
+                print(f"  {word}: {count}", file=sys.stderr)
+
+        if args.input == '-':
+            print("\nVocabulary statistics need an input file; skipped for stdin.", file=sys.stderr)
+            return 0
+        _print_vocabulary_stats(vocab_stats or _vocabulary_stats(processor, args))
+
+    return 0
+
+
+def _run_stats(processor: CVCProcessor, args) -> int:
+    """Handle the 'stats' subcommand."""
+    _configure_processor(processor, args)
+    vocab_stats = _vocabulary_stats(processor, args)
+
+    if args.stats_format == 'json' or args.stats_file:
+        _write_stats_json({'vocabulary': vocab_stats}, args.stats_file)
+    else:
+        _print_vocabulary_stats(vocab_stats)
+    return 0
+
+
+# Validation problems that only fail 'validate' under --strict
+METADATA_COUNT_KINDS = {'total_mappings_mismatch', 'total_synonyms_mismatch'}
+
+
+def _run_validate(processor: CVCProcessor, args) -> int:
+    """Handle the 'validate' subcommand."""
+    errors = []
+    for error in processor.validate():
+        if error.kind in METADATA_COUNT_KINDS and not args.strict:
+            print(f"warning: {error.kind}: {error.message}")
         else:
-            return canonical.lower()
+            print(f"{error.kind}: {error.message}")
+            errors.append(error)
 
-    def process_file(self, input_file: str, output_file: str) -> Dict:
-        """
-        Process an entire file with CVC transformation.
+    if errors:
+        print(f"\n{len(errors)} problem(s) found in {args.mapping}", file=sys.stderr)
+        return 1
 
-        Args:
-            input_file: Path to input file
-            output_file: Path to output file
+    print(f"{args.mapping} is valid", file=sys.stderr)
+    return 0
 
-        Returns:
-            Dictionary of processing statistics
-        """
-        with open(input_file, 'r') as f:
-            lines = f.readlines()
 
-        processed_lines = []
-        total_replacements = 0
-        total_words = 0
+def _run_batch(processor: CVCProcessor, args) -> int:
+    """Handle the 'batch' subcommand."""
+    _configure_processor(processor, args)
+    processor.fail_fast = args.fail_fast
 
-        for line in lines:
-            processed_line, stats = self.process_text(line.strip())
-            processed_lines.append(processed_line + '\n')
-            total_replacements += stats['replacements_made']
+    print(f"Processing {args.pattern} files under {args.input_dir}...", file=sys.stderr)
+    stats = processor.process_directory(args.input_dir, args.output_dir, args.pattern)
+    for skipped in stats['skipped']:
+        print(f"Warning: skipped {skipped['file']}: {skipped['reason']}", file=sys.stderr)
+    for error in stats['errors']:
+        print(f"Warning: {error['file']} line {error['line'] + 1} left unchanged: {error['error']}",
+              file=sys.stderr)
+
+    if args.stats_format == 'json' or args.stats_file:
+        _write_stats_json({'processing': stats}, args.stats_file)
+        return 0
+
+    print(f"\nProcessed {len(stats['files'])} file(s), skipped {len(stats['skipped'])}", file=sys.stderr)
+    print(f"Total lines: {stats['total_lines']}", file=sys.stderr)
+    print(f"Total words: {stats['total_words']}", file=sys.stderr)
+    print(f"Replacements made: {stats['total_replacements']}", file=sys.stderr)
+    print(f"Replacement rate: {stats['replacement_rate']:.2%}", file=sys.stderr)
+    if stats['invalid_sequences']:
+        print(f"Invalid UTF-8 sequences: {stats['invalid_sequences']}", file=sys.stderr)
+    return 0
+
+
+def _run_diff(processor: CVCProcessor, args) -> int:
+    """Handle the 'diff' subcommand."""
+    diff = diff_mappings(_load_mapping_file(args.old), _load_mapping_file(args.new))
+
+    for synonym, canonical in diff.added:
+        print(f"+ {synonym} -> {canonical}")
+    for synonym, canonical in diff.removed:
+        print(f"- {synonym} -> {canonical}")
+    for synonym, old_canonical, new_canonical in diff.reassigned:
+        print(f"~ {synonym}: {old_canonical} -> {new_canonical}")
+
+    print(f"\n{len(diff.added)} added, {len(diff.removed)} removed, "
+          f"{len(diff.reassigned)} reassigned", file=sys.stderr)
+    return 0
+
+
+def _run_expand(processor: CVCProcessor, args) -> int:
+    """Handle the 'expand' subcommand."""
+    _configure_processor(processor, args)
+    strategy = ExpandStrategy(args.strategy)
+    rng = random.Random(args.seed)
+
+    total_words = 0
+    total_replacements = 0
+    print(f"Expanding {_display_name(args.input)}...", file=sys.stderr)
+    with _open_stream(args.input, 'r', processor.invalid_utf8) as fin, \
+            _open_stream(args.output, 'w', processor.invalid_utf8) as fout:
+        for line in fin:
+            content, ending = _split_line_ending(line)
+            expanded_line, stats = processor.expand_text(content.strip(), strategy, rng=rng)
+            fout.write(expanded_line + ending)
             total_words += stats['total_words']
+            total_replacements += stats['replacements_made']
 
-        with open(output_file, 'w') as f:
-            f.writelines(processed_lines)
+    print(f"Expanded {total_replacements} of {total_words} words", file=sys.stderr)
+    return 0
 
-        return {
-            'input_file': input_file,
-            'output_file': output_file,
-            'total_lines': len(lines),
-            'total_words': total_words,
-            'total_replacements': total_replacements,
-            'replacement_rate': total_replacements / total_words if total_words else 0
-        }
 
-    def get_vocabulary_stats(self, text_file: str) -> Dict:
-        """
-        Analyze vocabulary statistics before and after CVC.
+def _run_suggest(processor: CVCProcessor, args) -> int:
+    """Handle the 'suggest' subcommand."""
+    candidates = processor.suggest_candidates(args.input, args.min_count)
+    for word, count in candidates:
+        print(f"{count}\t{word}")
 
-        Args:
-            text_file: Path to text file to analyze
+    print(f"\n{len(candidates)} unmapped word(s) occur at least {args.min_count} time(s)",
+          file=sys.stderr)
+    return 0
 
-        Returns:
-            Dictionary of vocabulary statistics
-        """
-        with open(text_file, 'r') as f:
-            text = f.read()
 
-        # Original vocabulary
-        original_words = re.findall(r'\w+', text.lower())
-        original_vocab = set(original_words)
+def _run_repl(processor: CVCProcessor, args) -> int:
+    """Handle the 'repl' subcommand."""
+    _configure_processor(processor, args)
+    interactive = sys.stdin.isatty()
+    if interactive:
+        print("Type text to process; Ctrl-D to exit.", file=sys.stderr)
 
-        # Process text
-        processed_text, _ = self.process_text(text)
-        processed_words = re.findall(r'\w+', processed_text.lower())
-        processed_vocab = set(processed_words)
+    while True:
+        if interactive:
+            print("> ", end='', file=sys.stderr, flush=True)
+        line = sys.stdin.readline()
+        if not line:
+            break
+        # A one-line stream, so each line goes through the same path as files
+        stats = processor.process_stream(io.StringIO(line), sys.stdout)
+        sys.stdout.flush()
+        print(f"  {stats['total_replacements']} of {stats['total_words']} words replaced "
+              f"({stats['replacement_rate']:.0%})", file=sys.stderr)
 
-        # Calculate statistics
-        vocab_reduction = len(original_vocab) - len(processed_vocab)
-        reduction_rate = vocab_reduction / len(original_vocab) if original_vocab else 0
+    if interactive:
+        print(file=sys.stderr)
+    return 0
 
-        return {
-            'original_vocabulary_size': len(original_vocab),
-            'processed_vocabulary_size': len(processed_vocab),
-            'vocabulary_reduction': vocab_reduction,
-            'reduction_rate': reduction_rate,
-            'total_words': len(original_words)
-        }
 
+def _run_serve(processor: CVCProcessor, args) -> int:
+    """Handle the 'serve' subcommand."""
+    _configure_processor(processor, args)
+    if args.max_request_bytes < 0:
+        raise CVCError("--max-request-bytes must be 0 or greater")
+    try:
+        server = make_server(processor, args.host, args.port, args.max_request_bytes)
+    except OSError as e:
+        raise CVCError(f"Cannot listen on {args.host}:{args.port}: {e}") from e
 
-def main():
-    """Main execution function."""
-    import argparse
+    host, port = server.server_address[:2]
+    print(f"Serving on http://{host}:{port} (POST /process, GET /health; Ctrl-C to stop)",
+          file=sys.stderr)
+    with server:
+        try:
+            server.serve_forever()
+        except KeyboardInterrupt:
+            pass
+    return 0
+
+
+# Subcommand name -> handler
+COMMANDS = {
+    'process': _run_process,
+    'stats': _run_stats,
+    'validate': _run_validate,
+    'expand': _run_expand,
+    'repl': _run_repl,
+    'serve': _run_serve,
+    'diff': _run_diff,
+    'batch': _run_batch,
+    'suggest': _run_suggest,
+}
+
+
+def _with_default_command(argv: List[str]) -> List[str]:
+    """Insert the 'process' subcommand for invocations that name none."""
+    global_parser = argparse.ArgumentParser(add_help=False)
+    global_parser.add_argument('--mapping')
+    global_parser.add_argument('--overlay', action='append')
+    _, rest = global_parser.parse_known_args(argv)
+
+    if rest and (rest[0] in COMMANDS or rest[0] in ('-h', '--help')):
+        return argv
+    return ['process'] + argv
 
+
+def main(argv: Optional[List[str]] = None) -> int:
+    """Main execution function."""
     parser = argparse.ArgumentParser(
         description='Apply Canonical Vocabulary Compression to text'
     )
     parser.add_argument(
         '--mapping',
-        default='mappings/synonym_to_canonical.json',
-        help='Path to synonym-to-canonical mapping file'
+        help=f'Path to synonym-to-canonical mapping file (default: {DEFAULT_MAPPING_FILE}, '
+             'falling back to the bundled dataset)'
     )
+
     parser.add_argument(
+        '--overlay',
+        action='append',
+        metavar='MAPPING',
+        help='Additional mapping file merged over --mapping; repeatable, later files win'
+    )
+
+    # Lets --mapping and --overlay also be given after the subcommand
+    common = argparse.ArgumentParser(add_help=False)
+    common.add_argument('--mapping', default=argparse.SUPPRESS, help=argparse.SUPPRESS)
+    common.add_argument('--overlay', action='append', default=argparse.SUPPRESS, help=argparse.SUPPRESS)
+
+    subparsers = parser.add_subparsers(dest='command', required=True)
+
+    process_parser = subparsers.add_parser(
+        'process', parents=[common], help='Apply CVC to a text file (default)'
+    )
+    process_parser.add_argument(
         '--input',
-        required=True,
-        help='Input text file to process'
+        default='-',
+        help="Input text file to process ('-' or omitted for stdin)"
     )
-    parser.add_argument(
+    process_parser.add_argument(
         '--output',
-        required=True,
-        help='Output file for processed text'
+        default='-',
+        help="Output file for processed text ('-' or omitted for stdout)"
     )
-    parser.add_argument(
+    process_parser.add_argument(
+        '--append',
+        action='store_true',
+        help='Add the processed text to the end of --output instead of overwriting it'
+    )
+    process_parser.add_argument(
+        '--in-place',
+        action='store_true',
+        help='Overwrite the input file with the processed text (written atomically)'
+    )
+    process_parser.add_argument(
         '--stats',
         action='store_true',
         help='Print vocabulary statistics'
     )
+    process_parser.add_argument(
+        '--format',
+        choices=['text', 'markdown', 'html', 'csv'],
+        default='text',
+        help='Input format; markdown leaves code and link URLs untouched, '
+             'html only rewrites text nodes, csv only rewrites --columns (default: text)'
+    )
+    process_parser.add_argument(
+        '--columns',
+        type=_column_list,
+        metavar='N[,N...]',
+        help='0-based CSV columns to process with --format csv (e.g. 1,3); '
+             'everything else is copied through verbatim'
+    )
+    process_parser.add_argument(
+        '--header',
+        action='store_true',
+        help='With --format csv, leave the first record (column names) unchanged'
+    )
+    process_parser.add_argument(
+        '--undo-log',
+        help='Write a JSONL undo log here so the input can be restored'
+    )
+    process_parser.add_argument(
+        '--replacements-log',
+        metavar='PATH',
+        help='Write each replacement as a JSON object per line here'
+    )
+    process_parser.add_argument(
+        '--watch',
+        action='store_true',
+        help='Keep running and reprocess the input file whenever it changes'
+    )
+    process_parser.add_argument(
+        '--progress',
+        action='store_true',
+        help='Show progress on stderr while processing'
+    )
+    process_parser.add_argument(
+        '--fail-fast',
+        action='store_true',
+        help='Abort on the first line that fails to process instead of leaving it unchanged'
+    )
+    process_parser.add_argument(
+        '--dry-run',
+        action='store_true',
+        help='Print the input with replacements marked inline instead of writing output'
+    )
+    _add_stats_output_arguments(process_parser)
+    _add_filter_arguments(process_parser)
+
+    stats_parser = subparsers.add_parser(
+        'stats', parents=[common], help='Print vocabulary statistics for a text file'
+    )
+    stats_parser.add_argument(
+        '--input',
+        required=True,
+        help='Input text file to analyze'
+    )
+    _add_stats_output_arguments(stats_parser)
+    _add_filter_arguments(stats_parser)
+
+    validate_parser = subparsers.add_parser(
+        'validate', parents=[common], help='Check a mapping file for consistency problems'
+    )
+    validate_parser.add_argument(
+        '--strict',
+        action='store_true',
+        help='Also fail on metadata total_mappings/total_synonyms mismatches'
+    )
+
+    batch_parser = subparsers.add_parser(
+        'batch', parents=[common], help='Apply CVC to every text file under a directory'
+    )
+    batch_parser.add_argument(
+        '--input-dir',
+        required=True,
+        help='Directory searched recursively for input files'
+    )
+    batch_parser.add_argument(
+        '--output-dir',
+        required=True,
+        help='Directory to write processed files to, mirroring the input tree'
+    )
+    batch_parser.add_argument(
+        '--pattern',
+        default='*.txt',
+        help='Glob pattern selecting input files (default: *.txt)'
+    )
+    batch_parser.add_argument(
+        '--fail-fast',
+        action='store_true',
+        help='Abort on the first line that fails to process instead of leaving it unchanged'
+    )
+    _add_stats_output_arguments(batch_parser)
+    _add_filter_arguments(batch_parser)
+
+    suggest_parser = subparsers.add_parser(
+        'suggest', parents=[common], help='List frequent words in a text file that have no mapping'
+    )
+    suggest_parser.add_argument(
+        '--input',
+        required=True,
+        help='Input text file to scan'
+    )
+    suggest_parser.add_argument(
+        '--min-count',
+        type=int,
+        default=5,
+        help='Minimum occurrences for a word to be listed (default: 5)'
+    )
+
+    diff_parser = subparsers.add_parser(
+        'diff', help='Show synonyms added, removed or reassigned between two mapping files'
+    )
+    diff_parser.add_argument('old', help='Original mapping file')
+    diff_parser.add_argument('new', help='Changed mapping file')
 
-    args = parser.parse_args()
+    expand_parser = subparsers.add_parser(
+        'expand', parents=[common], help='Rewrite canonical words back to synonyms'
+    )
+    expand_parser.add_argument(
+        '--input',
+        default='-',
+        help="Input text file to expand ('-' or omitted for stdin)"
+    )
+    expand_parser.add_argument(
+        '--output',
+        default='-',
+        help="Output file for expanded text ('-' or omitted for stdout)"
+    )
+    expand_parser.add_argument(
+        '--strategy',
+        choices=[strategy.value for strategy in ExpandStrategy],
+        default=ExpandStrategy.FIRST_SYNONYM.value,
+        help='How to choose a synonym for each canonical word'
+    )
+    expand_parser.add_argument(
+        '--seed',
+        type=int,
+        help='Random seed for the random strategy'
+    )
+    _add_filter_arguments(expand_parser)
+
+    repl_parser = subparsers.add_parser(
+        'repl', parents=[common], help='Process lines typed on stdin interactively'
+    )
+    _add_filter_arguments(repl_parser)
+
+    serve_parser = subparsers.add_parser(
+        'serve', parents=[common], help='Serve CVC processing over HTTP'
+    )
+    serve_parser.add_argument(
+        '--host',
+        default='127.0.0.1',
+        help='Interface to listen on (default: 127.0.0.1)'
+    )
+    serve_parser.add_argument(
+        '--port',
+        type=int,
+        default=8080,
+        help='Port to listen on (default: 8080)'
+    )
+    serve_parser.add_argument(
+        '--max-request-bytes',
+        type=int,
+        default=MAX_REQUEST_BYTES,
+        help=f'Largest POST body accepted; larger ones get 413 (default: {MAX_REQUEST_BYTES})'
+    )
+    _add_filter_arguments(serve_parser)
 
-    # Initialize processor
-    processor = CVCProcessor(args.mapping)
+    argv = sys.argv[1:] if argv is None else argv
+    args = parser.parse_args(_with_default_command(argv))
 
-    # Process file
-    print(f"Processing {args.input}...")
-    stats = processor.process_file(args.input, args.output)
+    # Like RUST_LOG: CVC_LOG=debug shows per-word lookup decisions on stderr
+    log_level = os.environ.get('CVC_LOG')
+    if log_level:
+        try:
+            logging.basicConfig(level=log_level.upper(), format='%(levelname)s %(name)s: %(message)s')
+        except ValueError:
+            print(f"Error: unknown CVC_LOG level '{log_level}'", file=sys.stderr)
+            return 1
 
-    print(f"\nProcessing complete!")
-    print(f"Total lines: {stats['total_lines']}")
-    print(f"Total words: {stats['total_words']}")
-    print(f"Replacements made: {stats['total_replacements']}")
-    print(f"Replacement rate: {stats['replacement_rate']:.2%}")
+    # Initialize processor, falling back to the bundled mappings
+    if args.mapping is None:
+        args.mapping = DEFAULT_MAPPING_FILE
+        if not os.path.exists(args.mapping):
+            args.mapping = str(BUNDLED_MAPPING_FILE)
 
-    if args.stats:
-        print("\nVocabulary Statistics:")
-        vocab_stats = processor.get_vocabulary_stats(args.input)
-        print(f"Original vocabulary size: {vocab_stats['original_vocabulary_size']}")
-        print(f"Processed vocabulary size: {vocab_stats['processed_vocabulary_size']}")
-        print(f"Vocabulary reduction: {vocab_stats['vocabulary_reduction']}")
-        print(f"Reduction rate: {vocab_stats['reduction_rate']:.2%}")
+    try:
+        if args.overlay:
+            processor = CVCProcessor.from_files([args.mapping] + args.overlay)
+        else:
+            processor = CVCProcessor(args.mapping)
+        # validate lists these itself
+        if processor.load_warnings and args.command != 'validate':
+            print(f"warning: {len(processor.load_warnings)} synonym(s) are listed under "
+                  f"multiple canonicals; run 'validate' for details", file=sys.stderr)
+        return COMMANDS[args.command](processor, args)
+    except CVCError as e:
+        print(f"Error: {e}", file=sys.stderr)
+        return 1
 
 
 if __name__ == '__main__':
-    main()
+    sys.exit(main())
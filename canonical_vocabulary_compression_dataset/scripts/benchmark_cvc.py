@@ -0,0 +1,186 @@
+#!/usr/bin/env python3
+"""
+CVC Performance Benchmark
+
+Measures CVCProcessor throughput to track performance regressions:
+- process_text on inputs of varying size and replacement density
+- Dictionary lookups vs. the compiled-pattern prefilter (use_automaton)
+- get_vocabulary_stats on a generated corpus file
+- Memory held by process_text results, with and without intern_canonicals
+"""
+
+import os
+import random
+import tempfile
+import timeit
+import tracemalloc
+from typing import Dict, List
+
+from apply_cvc import CVCProcessor
+
+
+# Common words unlikely to appear in the mappings, used as non-replaced filler
+FILLER_WORDS = [
+    'the', 'of', 'and', 'to', 'in', 'is', 'was', 'for', 'on', 'with',
+    'as', 'by', 'at', 'from', 'this', 'that', 'table', 'window', 'river',
+    'paper', 'garden', 'morning', 'letter', 'yellow', 'seven', 'under'
+]
+
+
+def generate_text(processor: CVCProcessor, num_words: int, density: float,
+                  seed: int = 0) -> str:
+    """
+    Generate benchmark text with a given share of mappable words.
+
+    Args:
+        processor: Processor whose synonyms are used as mappable words
+        num_words: Number of words to generate
+        density: Fraction of words (0-1) drawn from the mapped synonyms
+        seed: Random seed for reproducible inputs
+
+    Returns:
+        Space-separated text
+    """
+    rng = random.Random(seed)
+    filler = [w for w in FILLER_WORDS if not processor.contains(w)]
+    synonyms = sorted(
+        synonym for synonym, canonical in processor.reverse_lookup.items()
+        if ' ' not in synonym and synonym.lower() != canonical.lower()
+    )
+
+    words = [
+        rng.choice(synonyms) if rng.random() < density else rng.choice(filler)
+        for _ in range(num_words)
+    ]
+    return ' '.join(words)
+
+
+def time_call(func, repeat: int) -> float:
+    """Return the best wall-clock time in seconds over several runs."""
+    return min(timeit.repeat(func, number=1, repeat=repeat))
+
+
+def benchmark_process_text(processor: CVCProcessor, sizes: List[int],
+                           densities: List[float], repeat: int) -> List[Dict]:
+    """Time process_text for every size, density and lookup backend."""
+    results = []
+    for size in sizes:
+        for density in densities:
+            text = generate_text(processor, size, density)
+            for use_automaton in (False, True):
+                processor.use_automaton = use_automaton
+                processor.process_text(text)  # warm up (compiles the automaton)
+                seconds = time_call(lambda: processor.process_text(text), repeat)
+                results.append({
+                    'words': size,
+                    'density': density,
+                    'backend': 'automaton' if use_automaton else 'dictionary',
+                    'seconds': seconds,
+                    'words_per_second': size / seconds if seconds else 0
+                })
+    processor.use_automaton = False
+    return results
+
+
+def benchmark_vocabulary_stats(processor: CVCProcessor, num_words: int,
+                               repeat: int) -> Dict:
+    """Time get_vocabulary_stats on a generated corpus file."""
+    lines = [
+        generate_text(processor, 20, 0.1, seed=i) for i in range(num_words // 20)
+    ]
+
+    fd, path = tempfile.mkstemp(suffix='.txt')
+    try:
+        with os.fdopen(fd, 'w') as f:
+            f.write('\n'.join(lines) + '\n')
+        seconds = time_call(lambda: processor.get_vocabulary_stats(path), repeat)
+    finally:
+        os.remove(path)
+
+    return {
+        'words': num_words,
+        'seconds': seconds,
+        'words_per_second': num_words / seconds if seconds else 0
+    }
+
+
+def benchmark_memory(processor: CVCProcessor, num_words: int) -> List[Dict]:
+    """Measure memory retained by process_text results on a repetitive input."""
+    text = generate_text(processor, num_words, 0.5)
+    results = []
+    for intern in (False, True):
+        processor.intern_canonicals = intern
+        processor.process_text(text)  # warm up (fills the intern table)
+
+        tracemalloc.start()
+        before = tracemalloc.get_traced_memory()[0]
+        result = processor.process_text(text)
+        retained = tracemalloc.get_traced_memory()[0] - before
+        tracemalloc.stop()
+        del result
+
+        results.append({
+            'words': num_words,
+            'interned': intern,
+            'bytes': retained
+        })
+    processor.intern_canonicals = False
+    return results
+
+
+def main():
+    """Main execution function."""
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        description='Benchmark CVC processing throughput'
+    )
+    parser.add_argument(
+        '--mapping',
+        default='../mappings/synonym_to_canonical.json',
+        help='Path to synonym-to-canonical mapping file'
+    )
+    parser.add_argument(
+        '--sizes',
+        type=int,
+        nargs='+',
+        default=[100, 1000, 10000],
+        help='Input sizes in words (default: 100 1000 10000)'
+    )
+    parser.add_argument(
+        '--densities',
+        type=float,
+        nargs='+',
+        default=[0.0, 0.1, 0.5],
+        help='Fractions of mappable words (default: 0.0 0.1 0.5)'
+    )
+    parser.add_argument(
+        '--repeat',
+        type=int,
+        default=5,
+        help='Runs per measurement; the best is reported (default: 5)'
+    )
+
+    args = parser.parse_args()
+
+    processor = CVCProcessor(args.mapping)
+
+    print("\n=== process_text ===\n")
+    print(f"{'words':>8} {'density':>8} {'backend':>11} {'ms':>10} {'words/s':>12}")
+    for result in benchmark_process_text(processor, args.sizes, args.densities, args.repeat):
+        print(f"{result['words']:>8} {result['density']:>8.0%} {result['backend']:>11} "
+              f"{result['seconds'] * 1000:>10.2f} {result['words_per_second']:>12,.0f}")
+
+    print("\n=== get_vocabulary_stats ===\n")
+    vocab = benchmark_vocabulary_stats(processor, max(args.sizes), args.repeat)
+    print(f"{vocab['words']} words: {vocab['seconds'] * 1000:.2f} ms "
+          f"({vocab['words_per_second']:,.0f} words/s)")
+
+    print("\n=== process_text memory ===\n")
+    print(f"{'words':>8} {'interned':>9} {'KiB':>10}")
+    for result in benchmark_memory(processor, max(args.sizes)):
+        print(f"{result['words']:>8} {str(result['interned']):>9} {result['bytes'] / 1024:>10.1f}")
+
+
+if __name__ == '__main__':
+    main()
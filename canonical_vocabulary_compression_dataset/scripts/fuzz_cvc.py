@@ -0,0 +1,286 @@
+#!/usr/bin/env python3
+"""
+CVC Fuzz Harness
+
+Feeds random and mutated inputs to CVCProcessor to find crashes:
+- Mapping data: mutated JSON, YAML and TOML documents must either load or
+  raise CVCError, never any other exception
+- Text: random strings (mixed scripts, punctuation, whitespace and mapped
+  words) must process under every tokenizer and matching option without
+  raising, and the undo log must restore the input exactly
+
+Runs are reproducible from --seed; failing inputs are written to --crash-dir.
+"""
+
+import io
+import json
+import os
+import random
+import sys
+import traceback
+from typing import Dict, List, Optional
+
+from apply_cvc import CVCError, CVCProcessor, Tokenizer, apply_undo
+
+
+# Fragments spliced into mapping documents to reach parser edge cases
+SYNTAX_FRAGMENTS = [
+    '{', '}', '[', ']', '"', ':', ',', 'null', 'true', '1e999', '-0', '\\u0000',
+    '\\ud800', '[' * 200, '{"a":' * 50, '\n', '- ', '? ', '&a', '*a', '!!python/object',
+    '= ', '[[mappings]]', '"""', "'''"
+]
+
+# Replacement values for mutating parsed mapping data
+ODD_VALUES = [None, 0, -1, 1.5, True, '', ' ', 'x', [], {}, [''], [None], {'': ''}, ['a', 1]]
+
+# Characters random text is built from, grouped so each run mixes scripts
+TEXT_ALPHABETS = [
+    'abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ',
+    '0123456789',
+    ' \t\n\r\x0b\x0c  　',
+    '.,;:!?\'"-()[]{}/\\@#&*_’—¿',
+    'éñüÅıİßẞ',
+    '̧́̈⃝゙',
+    '一大猫あアーｶﾞ',
+    'абВαΣאا',
+    '\U0001f600\U0001f44d‍❤️�\x00\x7f',
+]
+
+# Option combinations process_text is fuzzed under
+TEXT_OPTIONS = [
+    {},
+    {'tokenizer': Tokenizer.UNICODE},
+    {'max_phrase_len': 3, 'split_compounds': True},
+    {'use_automaton': True, 'skip_urls': True},
+    {'tokenizer': Tokenizer.UNICODE, 'max_phrase_len': 3, 'skip_midsentence_capitalized': True},
+    {'accent_insensitive': True, 'fuzzy_distance': 1},
+    {'case_sensitive': True, 'skip_all_caps': True, 'min_word_len': 3},
+    {'split_trailing_digits': True, 'count_only_word_tokens': True, 'unicode_normalization': 'NFKC'},
+    {'resolve_chains': True, 'unicode_normalization': None},
+]
+
+
+def mapping_seeds(processor: CVCProcessor) -> Dict[str, str]:
+    """
+    Build valid seed documents in each supported format.
+
+    Args:
+        processor: Processor whose mappings (the first few) seed the documents
+
+    Returns:
+        Dictionary from format name to document text
+    """
+    mappings = dict(list(processor.to_mappings_data()['mappings'].items())[:5])
+    data = {'metadata': {'version': 'fuzz'}, 'mappings': mappings}
+
+    seeds = {'json': json.dumps(data, ensure_ascii=False, indent=1)}
+    try:
+        import yaml
+        seeds['yaml'] = yaml.safe_dump(data, allow_unicode=True)
+    except ImportError:
+        pass
+
+    toml = ['[metadata]', 'version = "fuzz"']
+    for key, info in mappings.items():
+        toml.append(f'\n[mappings.{json.dumps(key)}]')
+        toml.append(f"canonical = {json.dumps(info['canonical'])}")
+        toml.append(f"synonyms = {json.dumps(info['synonyms'])}")
+        if 'domain' in info:
+            toml.append(f"domain = {json.dumps(info['domain'])}")
+    seeds['toml'] = '\n'.join(toml) + '\n'
+    return seeds
+
+
+def mutate_bytes(data: bytes, rng: random.Random) -> bytes:
+    """Apply a few random byte-level edits (flips, splices, cuts, repeats)."""
+    data = bytearray(data)
+    for _ in range(rng.randint(1, 4)):
+        position = rng.randint(0, len(data))
+        action = rng.randrange(5)
+        if action == 0 and data:
+            data[min(position, len(data) - 1)] ^= 1 << rng.randrange(8)
+        elif action == 1:
+            data[position:position] = rng.choice(SYNTAX_FRAGMENTS).encode('utf-8')
+        elif action == 2:
+            del data[position:position + rng.randint(1, 16)]
+        elif action == 3:
+            data[position:position] = bytes(rng.randrange(256) for _ in range(rng.randint(1, 4)))
+        else:
+            data[position:position] = data[position:position + rng.randint(1, 32)]
+    return bytes(data)
+
+
+def mutate_structure(document: str, rng: random.Random) -> str:
+    """Replace a random value in a JSON document with a value of another type."""
+    data = json.loads(document)
+    node = data
+    for _ in range(rng.randint(0, 4)):
+        if not isinstance(node, (dict, list)) or not node:
+            break
+        key = rng.choice(list(node)) if isinstance(node, dict) else rng.randrange(len(node))
+        if rng.random() < 0.4 or not isinstance(node[key], (dict, list)):
+            node[key] = rng.choice(ODD_VALUES)
+            break
+        node = node[key]
+    else:
+        data = rng.choice(ODD_VALUES)
+    return json.dumps(data)
+
+
+def fuzz_mappings(seeds: Dict[str, str], iterations: int, rng: random.Random) -> List[Dict]:
+    """
+    Load mutated mapping documents, collecting any exception that is not a CVCError.
+
+    Returns:
+        List of failures, each with 'format', 'input' (bytes) and 'error'
+    """
+    failures = []
+    formats = sorted(seeds)
+    for _ in range(iterations):
+        fmt = rng.choice(formats)
+        if fmt == 'json' and rng.random() < 0.3:
+            data = mutate_structure(seeds[fmt], rng).encode('utf-8')
+        else:
+            data = mutate_bytes(seeds[fmt].encode('utf-8'), rng)
+
+        try:
+            processor = CVCProcessor.from_reader(io.BytesIO(data), fmt)
+            processor.process_text('a large quick test')
+            # Filters read the optional domain and frequency_rank fields
+            processor.set_active_domains(['general'])
+            processor.min_frequency_rank = processor.max_frequency_rank = 1
+            processor.process_text('a large quick test')
+        except CVCError:
+            pass
+        except Exception:
+            failures.append({'format': fmt, 'input': data, 'error': traceback.format_exc()})
+    return failures
+
+
+def random_text(words: List[str], rng: random.Random, max_len: int) -> str:
+    """Build random text mixing characters from several alphabets with mapped words."""
+    alphabets = rng.sample(TEXT_ALPHABETS, rng.randint(1, 4))
+    pieces = []
+    for _ in range(rng.randint(0, max_len)):
+        if words and rng.random() < 0.3:
+            word = rng.choice(words)
+            pieces.append(rng.choice([word, word.upper(), word.capitalize()]))
+        else:
+            pieces.append(rng.choice(rng.choice(alphabets)))
+        if rng.random() < 0.3:
+            pieces.append(rng.choice([' ', ' ', '\n', '. ', '-', "'s "]))
+    return ''.join(pieces)
+
+
+def check_text(processor: CVCProcessor, text: str) -> Optional[str]:
+    """Process text, returning a description of the problem found, if any."""
+    try:
+        processed, stats = processor.process_text(text)
+        reversible, _, undo = processor.process_text_reversible(text)
+        processor.process_text_lazy(text)
+    except Exception:
+        return traceback.format_exc()
+
+    for r in stats['replacements']:
+        if text[r['start']:r['end']] != r['original']:
+            return f"replacement span {r['start']}:{r['end']} does not hold {r['original']!r}"
+    if apply_undo(reversible, undo) != text:
+        return "undo log does not restore the input"
+    return None
+
+
+def fuzz_text(processor: CVCProcessor, iterations: int, rng: random.Random,
+              max_len: int) -> List[Dict]:
+    """
+    Process random text under each option set in TEXT_OPTIONS.
+
+    Returns:
+        List of failures, each with 'options', 'input' (str) and 'error'
+    """
+    words = sorted(processor.reverse_lookup) + sorted(processor.canonical_lookup)
+    failures = []
+    for i in range(iterations):
+        options = TEXT_OPTIONS[i % len(TEXT_OPTIONS)]
+        candidate = processor.clone()
+        for name, value in options.items():
+            setattr(candidate, name, value)
+
+        text = random_text(words, rng, max_len)
+        error = check_text(candidate, text)
+        if error:
+            failures.append({'options': options, 'input': text, 'error': error})
+    return failures
+
+
+def write_crashes(failures: List[Dict], crash_dir: str, kind: str):
+    """Write each failing input and its error to crash_dir."""
+    os.makedirs(crash_dir, exist_ok=True)
+    for i, failure in enumerate(failures):
+        base = os.path.join(crash_dir, f'{kind}-{i}')
+        data = failure['input']
+        with open(base + '.input', 'wb') as f:
+            f.write(data if isinstance(data, bytes) else data.encode('utf-8', 'surrogatepass'))
+        with open(base + '.txt', 'w') as f:
+            f.write(f"{failure.get('format') or failure.get('options')}\n\n{failure['error']}")
+
+
+def main():
+    """Main execution function."""
+    import argparse
+
+    parser = argparse.ArgumentParser(
+        description='Fuzz CVC mapping loading and text processing'
+    )
+    parser.add_argument(
+        '--mapping',
+        default='../mappings/synonym_to_canonical.json',
+        help='Path to synonym-to-canonical mapping file used for text and as the seed'
+    )
+    parser.add_argument(
+        '--iterations',
+        type=int,
+        default=2000,
+        help='Inputs to try for mapping loading and for text processing (default: 2000)'
+    )
+    parser.add_argument(
+        '--seed',
+        type=int,
+        default=0,
+        help='Random seed; a run is reproducible from it (default: 0)'
+    )
+    parser.add_argument(
+        '--max-len',
+        type=int,
+        default=60,
+        help='Maximum number of pieces in generated text (default: 60)'
+    )
+    parser.add_argument(
+        '--crash-dir',
+        default='fuzz_crashes',
+        help='Directory failing inputs are written to (default: fuzz_crashes)'
+    )
+
+    args = parser.parse_args()
+
+    processor = CVCProcessor(args.mapping)
+    rng = random.Random(args.seed)
+
+    print("\n=== mapping loading ===\n")
+    mapping_failures = fuzz_mappings(mapping_seeds(processor), args.iterations, rng)
+    print(f"{args.iterations} inputs, {len(mapping_failures)} failures")
+
+    print("\n=== process_text ===\n")
+    text_failures = fuzz_text(processor, args.iterations, rng, args.max_len)
+    print(f"{args.iterations} inputs, {len(text_failures)} failures")
+
+    if mapping_failures or text_failures:
+        write_crashes(mapping_failures, args.crash_dir, 'mapping')
+        write_crashes(text_failures, args.crash_dir, 'text')
+        first = (mapping_failures or text_failures)[0]
+        print(f"\nFailing inputs written to {args.crash_dir}; first error:\n\n{first['error']}")
+        return 1
+    return 0
+
+
+if __name__ == '__main__':
+    sys.exit(main())
@@ -0,0 +1,818 @@
+#!/usr/bin/env python3
+"""
+Tests for apply_cvc
+
+Run from this directory with: python -m unittest test_apply_cvc
+"""
+
+import contextlib
+import gzip
+import http.client
+import io
+import json
+import os
+import random
+import tempfile
+import threading
+import unicodedata
+import unittest
+from unittest import mock
+from pathlib import Path
+from typing import Optional
+
+from apply_cvc import (CVCProcessor, InputFileError, InvalidMappingError, Tokenizer, main,
+                       make_server, merge_statistics)
+
+
+# Small mapping set shared by the tests
+MAPPINGS = {
+    'metadata': {'version': 'test'},
+    'mappings': {
+        'size_big': {
+            'canonical': 'big',
+            'synonyms': ['large', 'huge', 'enormous'],
+            'domain': 'general',
+            'frequency_rank': 1
+        },
+        'quality_good': {
+            'canonical': 'good',
+            'synonyms': ['excellent', 'fine'],
+            'domain': 'general',
+            'frequency_rank': 2
+        },
+        'speed_fast': {
+            'canonical': 'fast',
+            'synonyms': ['quick', 'rapid'],
+            'domain': 'general',
+            'frequency_rank': 3
+        }
+    }
+}
+
+
+def make_processor(data=None, **options) -> CVCProcessor:
+    """Build a processor over MAPPINGS (or data) with options set."""
+    processor = CVCProcessor.from_mappings(data or MAPPINGS)
+    for name, value in options.items():
+        setattr(processor, name, value)
+    return processor
+
+
+class LineEndingTests(unittest.TestCase):
+    """Line terminators and whitespace survive file processing."""
+
+    MIXED = b'hello  world\r\n  indented line\nlast line without newline'
+
+    def process_bytes(self, data: bytes) -> bytes:
+        with tempfile.TemporaryDirectory() as directory:
+            input_file = os.path.join(directory, 'input.txt')
+            output_file = os.path.join(directory, 'output.txt')
+            with open(input_file, 'wb') as f:
+                f.write(data)
+            make_processor().process_file(input_file, output_file)
+            with open(output_file, 'rb') as f:
+                return f.read()
+
+    def test_unchanged_file_is_byte_identical(self):
+        self.assertEqual(self.process_bytes(self.MIXED), self.MIXED)
+
+    def test_mixed_endings_kept_around_replacements(self):
+        data = b'a  large dog\r\n\tquick fox\n\r\nhuge\r\n'
+        self.assertEqual(self.process_bytes(data), b'a  big dog\r\n\tfast fox\n\r\nbig\r\n')
+
+    def test_stream_keeps_endings(self):
+        out = io.StringIO(newline='')
+        stats = make_processor().process_stream(io.StringIO('large\r\nfine\n', newline=''), out)
+        self.assertEqual(out.getvalue(), 'big\r\ngood\n')
+        self.assertEqual(stats['total_lines'], 2)
+
+
+class VocabularyStatsTests(unittest.TestCase):
+    """get_vocabulary_stats_from_text counts the vocabulary of the real output."""
+
+    def test_partly_replaced_words_are_rebuilt(self):
+        processor = make_processor(split_trailing_digits=True)
+        stats = processor.get_vocabulary_stats_from_text('large2 huge3 dog')
+        # Output is 'big2 big3 dog'; 'big' alone never appears
+        self.assertEqual(stats['processed_vocabulary_size'], 3)
+
+    def test_whole_word_replacements(self):
+        stats = make_processor().get_vocabulary_stats_from_text('large huge big dog')
+        self.assertEqual(stats['original_vocabulary_size'], 4)
+        self.assertEqual(stats['processed_vocabulary_size'], 2)
+
+
+class PhraseCaseTests(unittest.TestCase):
+    """Phrase matches and capitalized canonicals keep the canonical's casing."""
+
+    DATA = {
+        'mappings': {
+            'city_nyc': {'canonical': 'NYC', 'synonyms': ['new york city', 'big apple', 'gotham']},
+            'size_big': {'canonical': 'big', 'synonyms': ['large']}
+        }
+    }
+
+    def test_phrase_keeps_canonical_casing(self):
+        processor = make_processor(self.DATA, max_phrase_len=3)
+        self.assertEqual(processor.process_text('New York City')[0], 'NYC')
+        self.assertEqual(processor.process_text('the big apple.')[0], 'the NYC.')
+        self.assertEqual(processor.process_text('NEW YORK CITY')[0], 'NYC')
+
+    def test_capitalized_canonical_for_single_word(self):
+        processor = make_processor(self.DATA)
+        self.assertEqual(processor.process_text('Gotham gotham')[0], 'NYC NYC')
+
+    def test_lowercase_canonical_follows_original(self):
+        processor = make_processor(self.DATA)
+        self.assertEqual(processor.process_text('Large large LARGE')[0], 'Big big BIG')
+
+
+class ContentReplacementRateTests(unittest.TestCase):
+    """mappable_words counts mapped words that filters kept unchanged."""
+
+    def test_nothing_held_back(self):
+        stats = make_processor().process_text('a large and quick dog')[1]
+        self.assertEqual(stats['mappable_words'], 2)
+        self.assertEqual(stats['content_replacement_rate'], 1.0)
+
+    def test_never_replace_is_held_back(self):
+        processor = make_processor()
+        processor.set_never_replace(['large'])
+        stats = processor.process_text('large huge dog, Large.')[1]
+        self.assertEqual(stats['replacements_made'], 1)
+        self.assertEqual(stats['mappable_words'], 3)
+
+    def test_domain_filter_is_held_back(self):
+        processor = make_processor()
+        processor.set_active_domains(['medical'])
+        stats = processor.process_text('large quick')[1]
+        self.assertEqual(stats['mappable_words'], 2)
+        self.assertEqual(stats['content_replacement_rate'], 0)
+
+
+class UnicodeTokenizerTests(unittest.TestCase):
+    """Tokenizer.UNICODE finds words in text without spaces."""
+
+    DATA = {
+        'mappings': {
+            'size_big': {'canonical': '大', 'synonyms': ['巨']},
+            'speed_fast': {'canonical': '速い', 'synonyms': ['迅速']},
+            'dessert': {'canonical': 'ケーキ', 'synonyms': ['タルト']},
+            'size_big_en': {'canonical': 'big', 'synonyms': ['large']}
+        }
+    }
+
+    def make(self, **options):
+        return make_processor(self.DATA, tokenizer=Tokenizer.UNICODE, **options)
+
+    def test_ideographs_are_single_words(self):
+        processed, stats = self.make().process_text('这是巨犬')
+        self.assertEqual(processed, '这是大犬')
+        self.assertEqual(stats['total_words'], 4)
+
+    def test_multi_character_keys_match_as_phrases(self):
+        processor = self.make(max_phrase_len=3)
+        self.assertEqual(processor.process_text('迅速な車 タルトです')[0], '速いな車 ケーキです')
+        # Without phrase matching the two-character key cannot match
+        self.assertEqual(self.make().process_text('迅速な車')[0], '迅速な車')
+
+    def test_latin_word_next_to_ideograph(self):
+        self.assertEqual(self.make().process_text('large猫, large dog')[0], 'big猫, big dog')
+
+
+class MappingFileTests(unittest.TestCase):
+    """Mapping files load from str and pathlib paths, compressed or not."""
+
+    def write_mappings(self, directory: str, name: str) -> Path:
+        path = Path(directory, name)
+        opener = gzip.open if name.endswith('.gz') else open
+        with opener(path, 'wt') as f:
+            json.dump(MAPPINGS, f)
+        return path
+
+    def test_path_objects(self):
+        with tempfile.TemporaryDirectory() as directory:
+            for name in ('mappings.json', 'mappings.json.gz'):
+                path = self.write_mappings(directory, name)
+                for mapping_file in (path, str(path)):
+                    processor = CVCProcessor(mapping_file)
+                    self.assertEqual(processor.process_text('large dog')[0], 'big dog')
+
+    def test_from_files_with_paths(self):
+        with tempfile.TemporaryDirectory() as directory:
+            path = self.write_mappings(directory, 'mappings.json.gz')
+            processor = CVCProcessor.from_files([path])
+            self.assertEqual(processor.process_text('quick')[0], 'fast')
+
+
+class CloneTests(unittest.TestCase):
+    """Clones and filtered processors have independent options."""
+
+    def test_clone_sets_are_independent(self):
+        processor = make_processor()
+        processor.set_active_domains(['general'])
+        clone = processor.clone()
+        clone.never_replace.add('large')
+        clone.active_domains.add('medical')
+        self.assertEqual(processor.never_replace, set())
+        self.assertEqual(processor.active_domains, {'general'})
+        self.assertEqual(processor.process_text('large')[0], 'big')
+        self.assertEqual(clone.process_text('large')[0], 'large')
+
+    def test_filtered_sets_are_independent(self):
+        processor = make_processor()
+        subset = processor.filtered(lambda info: info['canonical'] == 'big')
+        subset.never_replace.add('huge')
+        self.assertEqual(processor.process_text('huge')[0], 'big')
+        self.assertEqual(subset.process_text('huge large')[0], 'huge big')
+
+
+class InputFileErrorTests(unittest.TestCase):
+    """Unreadable and undecodable input files raise InputFileError."""
+
+    def setUp(self):
+        self.directory = tempfile.TemporaryDirectory()
+        self.addCleanup(self.directory.cleanup)
+        self.missing = os.path.join(self.directory.name, 'missing.txt')
+        self.invalid = os.path.join(self.directory.name, 'invalid.txt')
+        with open(self.invalid, 'wb') as f:
+            f.write(b'large \xff\xfe dog\n')
+        self.mapping_file = os.path.join(self.directory.name, 'mappings.json')
+        with open(self.mapping_file, 'w') as f:
+            json.dump(MAPPINGS, f)
+
+    def test_process_file(self):
+        output_file = os.path.join(self.directory.name, 'output.txt')
+        for input_file in (self.missing, self.invalid):
+            with self.assertRaises(InputFileError):
+                make_processor().process_file(input_file, output_file)
+
+    def test_file_analysis(self):
+        processor = make_processor()
+        for input_file in (self.missing, self.invalid):
+            with self.assertRaises(InputFileError):
+                processor.get_vocabulary_stats(input_file)
+            with self.assertRaises(InputFileError):
+                processor.suggest_candidates(input_file)
+
+    def test_invalid_utf8_option_allows_bad_bytes(self):
+        stats = make_processor(invalid_utf8='replace').get_vocabulary_stats(self.invalid)
+        self.assertEqual(stats['processed_vocabulary_size'], 2)
+
+    def test_cli_reports_error(self):
+        stderr = io.StringIO()
+        with contextlib.redirect_stderr(stderr):
+            status = main(['--mapping', self.mapping_file, 'process', '--input', self.missing])
+        self.assertEqual(status, 1)
+        self.assertIn('Error: Failed to read input file', stderr.getvalue())
+
+
+class CSVTests(unittest.TestCase):
+    """process_csv only rewrites cells of the chosen columns that changed."""
+
+    def process(self, text, columns, **options):
+        out = io.StringIO(newline='')
+        stats = make_processor().process_csv(io.StringIO(text, newline=''), out, columns, **options)
+        return out.getvalue(), stats
+
+    def test_untouched_fields_are_verbatim(self):
+        text = 'id,"note",extra\r\n"1","a large, quick dog","x ""y"""\r\n2,  fine ,"large"\r\n'
+        processed, stats = self.process(text, [1])
+        self.assertEqual(processed, 'id,"note",extra\r\n"1","a big, fast dog","x ""y"""\r\n'
+                                    '2,  good ,"large"\r\n')
+        self.assertEqual(stats['total_lines'], 3)
+        self.assertEqual(stats['total_replacements'], 3)
+
+    def test_quoted_field_spanning_lines(self):
+        processed, _ = self.process('1,"huge\nlarge ""quoted"""\n2,huge\n', [1])
+        self.assertEqual(processed, '1,"big\nbig ""quoted"""\n2,big\n')
+
+    def test_header_is_left_alone(self):
+        text = 'large,huge\nlarge,huge\n'
+        self.assertEqual(self.process(text, [0, 1])[0], 'big,big\nbig,big\n')
+        self.assertEqual(self.process(text, [0, 1], has_header=True)[0], 'large,huge\nbig,big\n')
+
+    def test_short_rows_and_unterminated_quote(self):
+        processed, _ = self.process('large\nx,"large\n', [1])
+        self.assertEqual(processed, 'large\nx,"large\n')
+
+
+class LazyProcessingTests(unittest.TestCase):
+    """process_text_lazy only builds new text when something is replaced."""
+
+    def test_unchanged_text_is_same_object(self):
+        text = 'a  small\tdog\n'
+        self.assertIs(make_processor().process_text_lazy(text), text)
+
+    def test_changed_text_keeps_whitespace(self):
+        processor = make_processor()
+        self.assertEqual(processor.process_text_lazy('a  large\tquick dog\n'), 'a  big\tfast dog\n')
+
+    def test_matches_process_text_words(self):
+        processor = make_processor(max_phrase_len=2, split_compounds=True, use_automaton=True)
+        text = 'The Large, quick-witted dog is FINE.'
+        self.assertEqual(processor.process_text_lazy(text), processor.process_text(text)[0])
+
+
+class IdempotenceTests(unittest.TestCase):
+    """Random text against a generated mapping: reprocessing is a no-op."""
+
+    SEED = 89
+
+    def setUp(self):
+        rng = random.Random(self.SEED)
+        words = [f'w{i}' for i in range(30)]
+        rng.shuffle(words)
+        self.mapped = words[:20]
+        self.unmapped = words[20:] + ['zz', 'it', 'x-y']
+        self.canonicals = [f'c{i}' for i in range(5)]
+        self.data = {'mappings': {
+            canonical: {'canonical': canonical, 'synonyms': self.mapped[i::5], 'domain': 'general'}
+            for i, canonical in enumerate(self.canonicals)
+        }}
+        self.rng = rng
+
+    def random_text(self) -> str:
+        pieces = []
+        for _ in range(self.rng.randint(0, 12)):
+            word = self.rng.choice(self.mapped + self.unmapped + self.canonicals)
+            word = self.rng.choice([str.lower, str.capitalize, str.upper])(word)
+            word = self.rng.choice(['', '(', '"']) + word + self.rng.choice(['', '.', ',', '!)'])
+            pieces.append(word + self.rng.choice([' ', '  ', '\t', '\n']))
+        return ''.join(pieces)
+
+    def test_processing_twice_changes_nothing(self):
+        for options in ({}, {'use_automaton': True}, {'tokenizer': Tokenizer.UNICODE},
+                        {'preserve_case': False}):
+            options = dict(options)
+            preserve_case = options.pop('preserve_case', True)
+            processor = make_processor(self.data, **options)
+            for _ in range(200):
+                once = processor.process_text(self.random_text(), preserve_case)[0]
+                self.assertEqual(processor.process_text(once, preserve_case)[0], once, options)
+
+    def test_unmapped_words_are_never_altered(self):
+        processor = make_processor(self.data)
+        for _ in range(200):
+            text = self.random_text()
+            processed, stats = processor.process_text(text)
+            for replacement in stats['replacements']:
+                self.assertIn(replacement['original'].lower(), processor.case_insensitive_lookup)
+            for original, result in zip(text.split(), processed.split()):
+                core = original.strip('()".,!')
+                if core.lower() not in processor.case_insensitive_lookup:
+                    self.assertEqual(result, original)
+
+    def test_chains_need_resolve_chains(self):
+        data = {'mappings': {
+            'a': {'canonical': 'bright', 'synonyms': ['vivid']},
+            'b': {'canonical': 'smart', 'synonyms': ['bright']}
+        }}
+        processor = make_processor(data)
+        self.assertEqual(processor.process_text('vivid')[0], 'bright')
+        self.assertEqual(processor.process_text('bright')[0], 'smart')
+
+        processor.resolve_chains = True
+        self.assertEqual(processor.process_text('vivid bright')[0], 'smart smart')
+        self.assertEqual(processor.process_text('smart smart')[0], 'smart smart')
+
+
+class LongestMatchTests(unittest.TestCase):
+    """The longest key starting at a word wins; equal lengths tie-break on case."""
+
+    DATA = {
+        'mappings': {
+            'city': {'canonical': 'NYC', 'synonyms': ['new york city']},
+            'city_title': {'canonical': 'Gotham', 'synonyms': ['New York City']},
+            'fresh': {'canonical': 'fresh', 'synonyms': ['new']},
+            'borough': {'canonical': 'yorkville', 'synonyms': ['york city']}
+        }
+    }
+
+    def setUp(self):
+        self.processor = make_processor(self.DATA, max_phrase_len=3)
+
+    def test_longest_key_wins(self):
+        self.assertEqual(self.processor.process_text('new york city')[0], 'NYC')
+        self.assertEqual(self.processor.process_text('new york')[0], 'fresh york')
+        self.assertEqual(self.processor.process_text('a york city, new.')[0], 'a yorkville, fresh.')
+
+    def test_scan_is_left_to_right(self):
+        # 'new' before the phrase is matched alone; 'york city' after it is free
+        self.assertEqual(self.processor.process_text('new new york city york city')[0],
+                         'fresh NYC yorkville')
+
+    def test_equal_length_tie_break(self):
+        self.assertEqual(self.processor.process_text('New York City')[0], 'Gotham')
+        # No exact-case key: the all-lowercase key wins
+        self.assertEqual(self.processor.process_text('NEW YORK CITY')[0], 'NYC')
+        self.assertEqual(self.processor.process_text('New york city')[0], 'NYC')
+
+
+class CompoundTests(unittest.TestCase):
+    """split_compounds processes hyphenated compounds and contractions."""
+
+    DATA = {
+        'mappings': {
+            'size_big': {'canonical': 'big', 'synonyms': ['large']},
+            'fame': {'canonical': 'famous', 'synonyms': ['well-known', 'known']},
+            'parent': {'canonical': 'mom', 'synonyms': ['mother']},
+            'rule': {'canonical': 'rule', 'synonyms': ['law']},
+            'pronoun': {'canonical': 'he', 'synonyms': ['it']}
+        }
+    }
+
+    def test_compounds_skipped_by_default(self):
+        processor = make_processor(self.DATA)
+        text = "a well-known mother-in-law, it's large-scale"
+        self.assertEqual(processor.process_text(text)[0], text)
+
+    def test_whole_compound_is_looked_up_first(self):
+        processor = make_processor(self.DATA, split_compounds=True)
+        self.assertEqual(processor.process_text('well-known')[0], 'famous')
+        self.assertEqual(processor.process_text('Well-known.')[0], 'Famous.')
+
+    def test_segments_are_looked_up_alone(self):
+        processor = make_processor(self.DATA, split_compounds=True)
+        self.assertEqual(processor.process_text('mother-in-law')[0], 'mom-in-rule')
+        processed, stats = processor.process_text('large-scale')
+        self.assertEqual(processed, 'big-scale')
+        self.assertEqual((stats['replacements'][0]['start'], stats['replacements'][0]['end']), (0, 5))
+
+    def test_contractions(self):
+        processor = make_processor(self.DATA, split_compounds=True)
+        self.assertEqual(processor.process_text("it's")[0], "he's")
+        self.assertEqual(processor.process_text("It’s")[0], "He’s")
+        # Only a trailing 's is split off
+        self.assertEqual(processor.process_text("it'll")[0], "it'll")
+
+
+class CaseSensitiveTests(unittest.TestCase):
+    """case_sensitive only matches keys exactly as written."""
+
+    DATA = {
+        'mappings': {
+            'size': {'canonical': 'large', 'synonyms': ['big']},
+            'gene': {'canonical': 'gene', 'synonyms': ['BRCA1']}
+        }
+    }
+
+    def test_default_falls_back_to_any_case(self):
+        self.assertEqual(make_processor(self.DATA).process_text('big BIG brca1')[0], 'large LARGE gene')
+
+    def test_case_sensitive_leaves_other_cases_alone(self):
+        processed, stats = make_processor(self.DATA, case_sensitive=True).process_text('big BIG Big')
+        self.assertEqual(processed, 'large BIG Big')
+        self.assertEqual(stats['replacements_made'], 1)
+
+    def test_case_sensitive_uppercase_key(self):
+        processor = make_processor(self.DATA, case_sensitive=True)
+        self.assertEqual(processor.process_text('BRCA1 brca1')[0], 'GENE brca1')
+
+
+class UnicodeNormalizationTests(unittest.TestCase):
+    """Composed and decomposed accents match each other unless disabled."""
+
+    COMPOSED = unicodedata.normalize('NFC', 'café')
+    DECOMPOSED = unicodedata.normalize('NFD', 'café')
+
+    def mappings(self, key):
+        return {'mappings': {'drink': {'canonical': 'coffee', 'synonyms': [key, 'ｔｅａ']}}}
+
+    def test_decomposed_word_matches_composed_key(self):
+        processor = make_processor(self.mappings(self.COMPOSED))
+        self.assertNotEqual(self.COMPOSED, self.DECOMPOSED)
+        self.assertEqual(processor.process_text(f'{self.DECOMPOSED}. {self.COMPOSED}')[0], 'coffee. coffee')
+
+    def test_composed_word_matches_decomposed_key(self):
+        processor = make_processor(self.mappings(self.DECOMPOSED))
+        self.assertEqual(processor.process_text(self.COMPOSED)[0], 'coffee')
+
+    def test_nfkc_folds_full_width_forms(self):
+        processor = make_processor(self.mappings(self.COMPOSED))
+        self.assertEqual(processor.process_text('tea')[0], 'tea')
+        processor.unicode_normalization = 'NFKC'
+        self.assertEqual(processor.process_text('tea')[0], 'coffee')
+
+    def test_disabled_for_byte_exact_matching(self):
+        processor = make_processor(self.mappings(self.COMPOSED), unicode_normalization=None)
+        self.assertEqual(processor.process_text(self.DECOMPOSED)[0], self.DECOMPOSED)
+        self.assertEqual(processor.process_text(self.COMPOSED)[0], 'coffee')
+
+
+class SkipUrlTests(unittest.TestCase):
+    """skip_urls passes URLs, email addresses and paths through untouched."""
+
+    TEXT = 'see https://large.com/huge, mail user@large.org or run /usr/large/bin: large'
+
+    def test_unicode_tokenizer_rewrites_inside_urls_by_default(self):
+        processor = make_processor(tokenizer=Tokenizer.UNICODE)
+        self.assertEqual(processor.process_text(self.TEXT)[0],
+                         'see https://big.com/big, mail user@big.org or run /usr/big/bin: big')
+
+    def test_unicode_tokenizer_skips_urls(self):
+        processed, stats = make_processor(tokenizer=Tokenizer.UNICODE, skip_urls=True).process_text(self.TEXT)
+        self.assertEqual(processed, self.TEXT[:-len('large')] + 'big')
+        self.assertEqual(stats['replacements_made'], 1)
+
+    def test_whitespace_tokenizer_skips_urls(self):
+        processor = make_processor(split_compounds=True, skip_urls=True)
+        processed, stats = processor.process_text('https://www.x.org/large-scale large-scale')
+        self.assertEqual(processed, 'https://www.x.org/large-scale big-scale')
+        self.assertEqual(stats['replacements_made'], 1)
+
+
+class SkipAllCapsTests(unittest.TestCase):
+    """skip_all_caps keeps all-caps words such as acronyms unchanged."""
+
+    def test_all_caps_is_skipped(self):
+        processor = make_processor(skip_all_caps=True)
+        processed, stats = processor.process_text('HUGE Corp, Huge huge HUGE.')
+        self.assertEqual(processed, 'HUGE Corp, Big big HUGE.')
+        self.assertEqual(stats['replacements_made'], 2)
+        self.assertEqual(stats['mappable_words'], 4)
+
+    def test_default_maps_all_caps(self):
+        self.assertEqual(make_processor().process_text('HUGE Huge huge')[0], 'BIG Big big')
+
+    def test_single_letters_still_map(self):
+        data = {'mappings': {**MAPPINGS['mappings'], 'one': {'canonical': 'one', 'synonyms': ['a']}}}
+        processor = make_processor(data, skip_all_caps=True)
+        self.assertEqual(processor.process_text('A HUGE dog', preserve_case=False)[0], 'one HUGE dog')
+
+
+class CaseFoldResolutionTests(unittest.TestCase):
+    """Keys colliding under case folding resolve the same regardless of order."""
+
+    US = {
+        'country': {'canonical': 'United States', 'synonyms': ['US', 'USA']},
+        'pronoun': {'canonical': 'we', 'synonyms': ['us']}
+    }
+
+    def processors(self, mappings):
+        for items in (list(mappings.items()), list(reversed(mappings.items()))):
+            yield make_processor({'mappings': dict(items)})
+
+    def test_exact_case_wins_then_lowercase_key(self):
+        for processor in self.processors(self.US):
+            processed = [processor.process_text(word, preserve_case=False)[0] for word in ('US', 'us', 'Us', 'uS')]
+            self.assertEqual(processed, ['United States', 'we', 'we', 'we'])
+
+    def test_first_sorted_key_without_lowercase_key(self):
+        mappings = {
+            'first': {'canonical': 'alpha', 'synonyms': ['AB']},
+            'second': {'canonical': 'beta', 'synonyms': ['Ab']}
+        }
+        for processor in self.processors(mappings):
+            processed = [processor.process_text(word, preserve_case=False)[0] for word in ('AB', 'Ab', 'ab', 'aB')]
+            self.assertEqual(processed, ['alpha', 'beta', 'alpha', 'alpha'])
+
+    def test_lookup_ambiguities(self):
+        for processor in self.processors(self.US):
+            self.assertEqual(processor.lookup_ambiguities(), {'us': {'US': 'United States', 'us': 'we'}})
+
+
+class MidsentenceCapitalizedTests(unittest.TestCase):
+    """skip_midsentence_capitalized keeps likely proper nouns mid-sentence."""
+
+    def test_sentence_initial_replaced_mid_sentence_kept(self):
+        for tokenizer in Tokenizer:
+            processor = make_processor(skip_midsentence_capitalized=True, tokenizer=tokenizer)
+            processed, stats = processor.process_text('Large dogs met Mr Large today.')
+            self.assertEqual(processed, 'Big dogs met Mr Large today.')
+            self.assertEqual(stats['mappable_words'], 2)
+
+    def test_sentence_boundaries(self):
+        processor = make_processor(skip_midsentence_capitalized=True, tokenizer=Tokenizer.UNICODE)
+        text = 'It was large. Large dogs came! "Huge," she said? Huge\nLarge'
+        self.assertEqual(processor.process_text(text)[0],
+                         'It was big. Big dogs came! "Big," she said? Big\nBig')
+
+    def test_period_after_abbreviation_or_initial_does_not_start_sentence(self):
+        for tokenizer in Tokenizer:
+            processor = make_processor(skip_midsentence_capitalized=True, tokenizer=tokenizer)
+            text = 'Large dogs met Dr. Large, Mr. Large and J. Large. Large ones, e.g. Large, stayed.'
+            self.assertEqual(processor.process_text(text)[0],
+                             'Big dogs met Dr. Large, Mr. Large and J. Large. Big ones, e.g. Large, stayed.')
+
+    def test_lowercase_and_all_caps_still_map(self):
+        processor = make_processor(skip_midsentence_capitalized=True)
+        self.assertEqual(processor.process_text('the large and LARGE dog')[0], 'the big and BIG dog')
+
+    def test_off_by_default(self):
+        self.assertEqual(make_processor().process_text('met Mr Large')[0], 'met Mr Big')
+
+
+class MergeStatisticsTests(unittest.TestCase):
+    """merge_statistics over chunks matches one pass over the whole text."""
+
+    CHUNKS = ['A large dog.', '', 'Quick, huge and fine', 'nothing here', 'enormous rapid']
+
+    def test_merged_equals_single_pass(self):
+        processor = make_processor()
+        for tokenizer in Tokenizer:
+            processor.tokenizer = tokenizer
+            whole = processor.process_text('\n'.join(self.CHUNKS))[1]
+            merged = merge_statistics([(chunk, processor.process_text(chunk)[1]) for chunk in self.CHUNKS],
+                                      separator='\n')
+            self.assertEqual(merged['replacement_rate'], whole['replacement_rate'])
+            self.assertEqual(merged, whole)
+
+    def test_no_parts(self):
+        merged = merge_statistics([])
+        self.assertEqual((merged['total_words'], merged['replacement_rate']), (0, 0))
+
+
+class AddMappingTests(unittest.TestCase):
+    """add_mapping keeps the mappings free of self-maps and chains."""
+
+    def test_adds_synonym(self):
+        processor = make_processor()
+        processor.add_mapping('massive', 'big')
+        processor.add_mapping('speedy', 'swift')
+        self.assertEqual(processor.process_text('massive speedy dog')[0], 'big swift dog')
+
+    def test_rejects_chains_and_self_maps(self):
+        processor = make_processor()
+        for synonym, canonical in (('foo', 'large'), ('foo', 'Huge'), ('dog', 'Dog'), ('big', 'foo')):
+            with self.assertRaises(InvalidMappingError, msg=(synonym, canonical)):
+                processor.add_mapping(synonym, canonical)
+        self.assertEqual(processor.process_text('foo large')[0], 'foo big')
+
+
+class MappingValidationTests(unittest.TestCase):
+    """Malformed optional mapping fields are rejected when loading."""
+
+    def load(self, **fields):
+        info = {'canonical': 'big', 'synonyms': ['large'], **fields}
+        return CVCProcessor.from_reader(io.StringIO(json.dumps({'mappings': {'size': info}})))
+
+    def test_valid_optional_fields(self):
+        processor = self.load(domain='general', frequency_rank=1)
+        self.assertEqual(processor.process_text('large')[0], 'big')
+
+    def test_unknown_format_rejected(self):
+        data = json.dumps({'mappings': {'size': {'canonical': 'big', 'synonyms': ['large']}}})
+        self.assertEqual(CVCProcessor.from_reader(io.StringIO(data), 'json').process_text('large')[0], 'big')
+        for fmt in ('xml', 'JSON', ''):
+            with self.assertRaises(ValueError, msg=fmt):
+                CVCProcessor.from_reader(io.StringIO(data), fmt)
+
+    def test_bad_domain_and_rank(self):
+        for fields in ({'domain': []}, {'domain': 1}, {'frequency_rank': 'high'},
+                       {'frequency_rank': 1.5}, {'frequency_rank': True}):
+            with self.assertRaises(InvalidMappingError, msg=fields):
+                self.load(**fields)
+
+
+class ServerTests(unittest.TestCase):
+    """make_server validates Content-Length before reading a request body."""
+
+    def post(self, body: bytes, length: Optional[str], max_request_bytes: int = 100):
+        server = make_server(make_processor(), port=0, max_request_bytes=max_request_bytes)
+        threading.Thread(target=server.serve_forever, args=(0.05,), daemon=True).start()
+        self.addCleanup(server.server_close)
+        self.addCleanup(server.shutdown)
+
+        connection = http.client.HTTPConnection(*server.server_address[:2], timeout=5)
+        self.addCleanup(connection.close)
+        connection.putrequest('POST', '/process')
+        if length is not None:
+            connection.putheader('Content-Length', length)
+        connection.endheaders(body)
+        response = connection.getresponse()
+        return response.status, json.loads(response.read())
+
+    def test_valid_request(self):
+        body = json.dumps({'text': 'a large dog'}).encode('utf-8')
+        status, response = self.post(body, str(len(body)))
+        self.assertEqual((status, response['text']), (200, 'a big dog'))
+
+    def test_bad_content_length(self):
+        for length in ('-1', 'abc', '+5', '1_0', '²', ''):
+            self.assertEqual(self.post(b'', length)[0], 400, length)
+
+    def test_missing_content_length(self):
+        self.assertEqual(self.post(b'', None)[0], 411)
+
+    def test_body_over_limit(self):
+        body = json.dumps({'text': 'large ' * 50}).encode('utf-8')
+        status, response = self.post(body, str(len(body)))
+        self.assertEqual(status, 413)
+        self.assertIn('100 bytes', response['error'])
+
+    def test_deeply_nested_json(self):
+        body = b'[' * 100000
+        self.assertEqual(self.post(body, str(len(body)), max_request_bytes=len(body))[0], 400)
+
+
+class MatchedVocabularyTests(unittest.TestCase):
+    """matched_vocabulary and detect_domain agree with process_text's options."""
+
+    DATA = {
+        'mappings': {
+            **MAPPINGS['mappings'],
+            'medicine': {'canonical': 'drug', 'synonyms': ['medication'], 'domain': 'medical'}
+        }
+    }
+
+    def test_matches_process_text(self):
+        processor = make_processor(self.DATA)
+        text = 'A large, quick dog took medication; big fast dogs.'
+        self.assertEqual(processor.matched_vocabulary(text), {'large', 'quick', 'medication'})
+        self.assertEqual(processor.detect_domain(text), [('general', 2), ('medical', 1)])
+
+    def test_follows_matching_options(self):
+        text = 'Mr Large said: large-scale medication (huge).'
+        cases = [
+            ({}, {'Large', 'medication', 'huge'}),
+            ({'split_compounds': True}, {'Large', 'large', 'medication', 'huge'}),
+            ({'skip_midsentence_capitalized': True}, {'medication', 'huge'}),
+            ({'tokenizer': Tokenizer.UNICODE}, {'Large', 'large', 'medication', 'huge'}),
+            ({'tokenizer': Tokenizer.UNICODE, 'skip_midsentence_capitalized': True},
+             {'large', 'medication', 'huge'}),
+        ]
+        for options, expected in cases:
+            processor = make_processor(self.DATA, **options)
+            processed, stats = processor.process_text(text)
+            self.assertEqual(processor.matched_vocabulary(text), expected, options)
+            self.assertEqual(processor.matched_vocabulary(text),
+                             {r['original'] for r in stats['replacements']}, options)
+
+    def test_detect_domain_follows_tokenizer(self):
+        processor = make_processor(self.DATA, tokenizer=Tokenizer.UNICODE)
+        self.assertEqual(processor.detect_domain('medication,medication/large'),
+                         [('medical', 2), ('general', 1)])
+        self.assertEqual(make_processor(self.DATA).detect_domain('medication,medication/large'), [])
+
+
+class ProcessDirectoryTests(unittest.TestCase):
+    """process_directory never loses input files, even when writing over them."""
+
+    def setUp(self):
+        self.directory = tempfile.TemporaryDirectory()
+        self.addCleanup(self.directory.cleanup)
+        self.root = Path(self.directory.name)
+        (self.root / 'sub').mkdir()
+        (self.root / 'a.txt').write_bytes(b'large dog\n')
+        (self.root / 'sub' / 'b.txt').write_bytes(b'a quick\r\nfox')
+        (self.root / 'bad.txt').write_bytes(b'large \xff\n')
+
+    def test_same_directory_replaces_files(self):
+        stats = make_processor().process_directory(str(self.root), str(self.root))
+        self.assertEqual((self.root / 'a.txt').read_bytes(), b'big dog\n')
+        self.assertEqual((self.root / 'sub' / 'b.txt').read_bytes(), b'a fast\r\nfox')
+        self.assertEqual(stats['total_words'], 5)
+        # The undecodable file is skipped and kept as it was
+        self.assertEqual((self.root / 'bad.txt').read_bytes(), b'large \xff\n')
+        self.assertEqual([s['file'] for s in stats['skipped']], [str(self.root / 'bad.txt')])
+        self.assertEqual(sorted(p.name for p in self.root.rglob('*')), ['a.txt', 'b.txt', 'bad.txt', 'sub'])
+
+    def test_output_inside_input_is_not_reprocessed(self):
+        output_dir = self.root / 'out'
+        processor = make_processor()
+        processor.process_directory(str(self.root), str(output_dir))
+        stats = processor.process_directory(str(self.root), str(output_dir))
+        self.assertEqual(len(stats['files']), 2)
+        self.assertFalse((output_dir / 'out').exists())
+        self.assertEqual((output_dir / 'a.txt').read_bytes(), b'big dog\n')
+        self.assertEqual((self.root / 'a.txt').read_bytes(), b'large dog\n')
+
+
+class FuzzyMatchTests(unittest.TestCase):
+    """fuzzy_distance corrects misspellings with a bounded lookup cache."""
+
+    def test_misspellings_corrected(self):
+        processor = make_processor(fuzzy_distance=1)
+        self.assertEqual(processor.process_text('a larrge dog, a bigg dog, a cat')[0],
+                         'a big dog, a big dog, a cat')
+
+    def test_cache_evicts_least_recently_used(self):
+        processor = make_processor(fuzzy_distance=1)
+        with mock.patch('apply_cvc.FUZZY_CACHE_SIZE', 2):
+            processor.process_text('lagre hugee lagre quik')
+        cache = processor._fuzzy_index[2]
+        self.assertEqual(list(cache), [('lagre', 1), ('quik', 1)])
+
+
+class LookupTests(unittest.TestCase):
+    """contains and canonical_for agree with what process_text replaces."""
+
+    def test_synonyms_found(self):
+        processor = make_processor()
+        self.assertTrue(processor.contains('Large'))
+        self.assertEqual(processor.canonical_for('Large'), 'big')
+        self.assertFalse(processor.contains('dog'))
+        self.assertIsNone(processor.canonical_for('dog'))
+
+    def test_canonical_words_are_not_replaced(self):
+        processor = make_processor()
+        for word in ('big', 'Big', 'BIG'):
+            self.assertIn(word.lower(), processor.reverse_lookup)
+            self.assertFalse(processor.contains(word), word)
+            self.assertIsNone(processor.canonical_for(word), word)
+            self.assertEqual(processor.process_text(word)[0], word)
+
+
+if __name__ == '__main__':
+    unittest.main()